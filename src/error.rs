@@ -0,0 +1,100 @@
+//! Error type shared by the query helpers
+use crate::overpass::BBoxError;
+use std::fmt;
+
+/// Errors returned by osm-rs
+#[derive(Debug)]
+pub enum Error {
+    /// HTTP or decoding error from reqwest
+    Http(reqwest::Error),
+    /// The response body is not the expected JSON
+    Json(serde_json::Error),
+    /// The response body exceeded the configured limit in bytes
+    ResponseTooLarge(usize),
+    /// The query returned no results
+    Empty,
+    /// Nominatim found nothing at the location, with its explanation, e.g.
+    /// `Unable to geocode` for a point in the ocean
+    NotFound(String),
+    /// The query is malformed
+    InvalidQuery(String),
+    /// The configuration is invalid
+    InvalidConfig(String),
+    /// The bounding box is invalid
+    BBox(BBoxError),
+    /// The URL looks like it belongs to the other service, e.g. a Nominatim
+    /// config pointed at an Overpass interpreter
+    LikelyWrongEndpoint(String),
+    /// Overpass answered with an error page instead of data
+    Overpass(String),
+    /// Reading or writing a local file failed
+    Io(std::io::Error),
+    /// The request was abandoned through its cancellation token
+    Cancelled,
+    /// The server is throttling or overloaded and answered with a 429 or
+    /// 5xx `status`, asking to wait `retry_after` when it sent `Retry-After`
+    Unavailable {
+        status: u16,
+        retry_after: Option<std::time::Duration>,
+    },
+    /// The response body is not the expected XML. Only returned with the
+    /// `xml` feature, but always present so enabling it changes no types.
+    Xml(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "http error: {}", e),
+            Error::Json(e) => write!(f, "json error: {}", e),
+            Error::ResponseTooLarge(n) => write!(f, "response larger than {} bytes", n),
+            Error::Empty => write!(f, "no results"),
+            Error::NotFound(m) => write!(f, "not found: {}", m),
+            Error::InvalidQuery(m) => write!(f, "invalid query: {}", m),
+            Error::InvalidConfig(m) => write!(f, "invalid config: {}", m),
+            Error::BBox(e) => write!(f, "invalid bounding box: {}", e),
+            Error::LikelyWrongEndpoint(u) => write!(f, "likely wrong endpoint: {}", u),
+            Error::Overpass(m) => write!(f, "overpass error: {}", m),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Cancelled => write!(f, "request cancelled"),
+            Error::Unavailable { status, .. } => write!(f, "server unavailable: {}", status),
+            Error::Xml(m) => write!(f, "xml error: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::BBox(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<BBoxError> for Error {
+    fn from(e: BBoxError) -> Self {
+        Error::BBox(e)
+    }
+}
@@ -0,0 +1,132 @@
+//! HTTP helpers shared by the query modules
+use crate::registry::EndpointRegistry;
+use crate::retry;
+use crate::Error;
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::{Duration, SystemTime};
+
+/// Default user agent string
+pub(crate) static APP_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+
+/// Build a client for requests to `url`, identifying itself as
+/// `user_agent`, the one registered for the host in
+/// [`EndpointRegistry::global`] or [`APP_USER_AGENT`], in that order. It gives
+/// up on requests after `timeout` and follows at most `max_redirects`
+/// redirects (reqwest's default of 10 when unset). With `compression` it
+/// accepts the encodings enabled by the `gzip` and `deflate` features.
+pub(crate) fn client(
+    url: &str,
+    user_agent: Option<&str>,
+    timeout: Option<Duration>,
+    max_redirects: Option<usize>,
+    compression: bool,
+) -> Result<Client, Error> {
+    let defaults = EndpointRegistry::global().get(url).unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    for (k, v) in &defaults.headers {
+        let name = HeaderName::from_bytes(k.as_bytes())
+            .map_err(|e| Error::InvalidConfig(format!("bad header name `{}`: {}", k, e)))?;
+        let value = HeaderValue::from_str(v)
+            .map_err(|e| Error::InvalidConfig(format!("bad value for header `{}`: {}", k, e)))?;
+        headers.insert(name, value);
+    }
+    let user_agent = user_agent
+        .or(defaults.user_agent.as_deref())
+        .unwrap_or(APP_USER_AGENT);
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .default_headers(headers);
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    #[cfg(feature = "gzip")]
+    {
+        builder = builder.gzip(compression);
+    }
+    #[cfg(feature = "deflate")]
+    {
+        builder = builder.deflate(compression);
+    }
+    #[cfg(not(any(feature = "gzip", feature = "deflate")))]
+    let _ = compression;
+    if let Some(max) = max_redirects {
+        builder = builder.redirect(match max {
+            0 => Policy::none(),
+            // reqwest counts the original request as well
+            n => Policy::limited(n + 1),
+        });
+    }
+    Ok(builder.build()?)
+}
+
+/// [`Error::Unavailable`] when `res` has a 429 or 5xx status, with the wait
+/// asked for by its `Retry-After` header
+pub(crate) fn unavailable(res: &Response) -> Option<Error> {
+    let status = res.status();
+    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+        return None;
+    }
+    let retry_after = res
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| retry::parse_retry_after(v, SystemTime::now()));
+    Some(Error::Unavailable {
+        status: status.as_u16(),
+        retry_after,
+    })
+}
+
+/// Read the whole response body, aborting once it grows past `limit` bytes
+pub(crate) async fn read_body(mut res: Response, limit: Option<usize>) -> Result<Vec<u8>, Error> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok(res.bytes().await?.to_vec()),
+    };
+    if res.content_length().is_some_and(|len| len as usize > limit) {
+        return Err(Error::ResponseTooLarge(limit));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(Error::ResponseTooLarge(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Decode `body` with the charset named in `content_type`, falling back to UTF-8
+pub(crate) fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(|t| {
+            t.split(';').skip(1).find_map(|p| {
+                let (k, v) = p.split_once('=')?;
+                k.trim()
+                    .eq_ignore_ascii_case("charset")
+                    .then(|| v.trim().trim_matches('"'))
+            })
+        })
+        .and_then(|c| Encoding::for_label(c.as_bytes()))
+        .unwrap_or(UTF_8);
+    encoding.decode(body).0.into_owned()
+}
+
+/// Parse the response body as JSON, decoding it according to its `Content-Type`
+pub(crate) async fn json<T: DeserializeOwned>(res: Response) -> Result<T, Error> {
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = res.bytes().await?;
+    Ok(serde_json::from_str(&decode(
+        &body,
+        content_type.as_deref(),
+    ))?)
+}
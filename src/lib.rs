@@ -8,7 +8,7 @@
 //!
 //! # Bounding Boxes
 //! ```rust
-//! use osm_rs::overpass::{BoundingBox, Config};
+//! use osm_rs::overpass::{BoundingBox, Config, Search};
 //!
 //! #[tokio::main]
 //! async fn main() {
@@ -17,13 +17,14 @@
 //!       timeout: 25,
 //!       key: "amenity".to_string(),
 //!       val: "cafe".to_string(),
+//!       ..Default::default()
 //!   };
 //!
 //!   let b: BoundingBox = BoundingBox {
-//!       xmin: 51.305219521963295,
-//!       ymin: -0.7690429687500001,
-//!       xmax: 51.82219818336938,
-//!       ymax: 0.5273437500000064,
+//!       xmin: -0.7690429687500001,
+//!       ymin: 51.305219521963295,
+//!       xmax: 0.5273437500000064,
+//!       ymax: 51.82219818336938,
 //!   };
 //!
 //!   let resp = b.search(&c).await.expect("failed query");
@@ -38,6 +39,7 @@
 //!     let c: Config = Config {
 //!         url: "https://nominatim.openstreetmap.org/search".to_string(),
 //!         timeout: 25,
+//!         ..Default::default()
 //!     };
 //!
 //!     let g = Geocode {
@@ -48,11 +50,32 @@
 //!         state: None,
 //!         country: None,
 //!         postalcode: None,
+//!         ..Default::default()
 //!     };
 //!
-//!     let resp = g.search(&c).await.unwrap();
-//!     assert_eq!(resp[0].lat, 42.3554334);
-//!     assert_eq!(resp[0].lon, -71.060511);
+//!     let resp = g.search_one(&c).await.unwrap();
+//!     assert_eq!(resp.lat, 42.3554334);
+//!     assert_eq!(resp.lon, -71.060511);
+//! }
+//! ```
+//! # Search in a place
+//! ```rust
+//! use osm_rs::{nominatim, overpass, search_in_place};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let nc = nominatim::Config {
+//!         url: "https://nominatim.openstreetmap.org/search".to_string(),
+//!         timeout: 25,
+//!         ..Default::default()
+//!     };
+//!     let oc = overpass::Config {
+//!         key: "amenity".to_string(),
+//!         val: "cafe".to_string(),
+//!         ..Default::default()
+//!     };
+//!
+//!     let resp = search_in_place("Cambridge, MA", &nc, &oc).await.unwrap();
 //! }
 //! ```
 //! # Reverse geocode
@@ -63,11 +86,13 @@
 //!    let c: Config = Config {
 //!        url: "https://nominatim.openstreetmap.org/reverse".to_string(),
 //!        timeout: 25,
+//!        ..Default::default()
 //!    };
 //!
 //!    let g = ReverseGeocode {
 //!        lat: 42.3554334,
 //!        lon: -71.060511,
+//!        ..Default::default()
 //!    };
 //!
 //!    let resp = g.search(&c).await.unwrap();
@@ -75,5 +100,92 @@
 //! }
 //! ```
 
+pub mod error;
+mod http;
+#[cfg(test)]
+mod mock;
 pub mod nominatim;
 pub mod overpass;
+pub mod ratelimit;
+pub mod registry;
+pub mod retry;
+pub mod session;
+#[cfg(feature = "xml")]
+mod xml;
+
+pub use error::Error;
+use overpass::Search;
+pub use session::Session;
+
+/// Geocode `place` and search for nodes matching `overpass_config` inside the
+/// bounding box of the top result
+pub async fn search_in_place(
+    place: &str,
+    nominatim_config: &nominatim::Config,
+    overpass_config: &overpass::Config,
+) -> Result<overpass::OverpassResponse, Error> {
+    let g = nominatim::Geocode::new(place.to_string());
+    let top = g.search_one(nominatim_config).await?;
+    let resp = top.bbox().search(overpass_config).await?;
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_search_in_place() {
+        let nc = nominatim::Config {
+            url: "https://nominatim.openstreetmap.org/search".to_string(),
+            timeout: 25,
+            ..Default::default()
+        };
+        let oc = overpass::Config {
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            ..Default::default()
+        };
+
+        let resp = search_in_place("Cambridge, MA", &nc, &oc).await.unwrap();
+        assert!(!resp.elements.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_in_place_offline() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let server = MockServer::with_handler(move |req| {
+            log.lock().unwrap().push(req.clone());
+            if req.target.starts_with("/search") {
+                MockResponse::json(&format!(
+                    "[{}]",
+                    include_str!("../fixtures/nominatim_boston.json")
+                ))
+            } else {
+                MockResponse::json(include_str!("../fixtures/overpass_london_cafes.json"))
+            }
+        })
+        .await;
+        let nc = nominatim::Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let oc = overpass::Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            ..Default::default()
+        };
+
+        let resp = search_in_place("Boston", &nc, &oc).await.unwrap();
+        assert_eq!(resp.elements.len(), 2);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].query("q"), Some("Boston"));
+        assert!(seen[1]
+            .body
+            .contains("(42.2279112,-71.1912442,42.4008613,-70.8044881)"));
+    }
+}
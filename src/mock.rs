@@ -0,0 +1,198 @@
+//! Minimal HTTP server serving canned responses for offline tests
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Canned response returned by [`MockServer`]
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Wait this long before answering, e.g. to keep requests in flight
+    pub delay: Option<Duration>,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            delay: None,
+        }
+    }
+
+    /// A 200 response with a JSON body
+    pub fn json(body: &str) -> Self {
+        Self::new(200, body).header("Content-Type", "application/json")
+    }
+
+    pub fn header(mut self, key: &str, val: &str) -> Self {
+        self.headers.push((key.to_string(), val.to_string()));
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+/// Request received by [`MockServer`]
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    /// Path and query string
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockRequest {
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Value of query parameter `key`, still percent-encoded
+    pub fn query(&self, key: &str) -> Option<&str> {
+        let (_, query) = self.target.split_once('?')?;
+        query
+            .split('&')
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+/// Local HTTP server answering every request with a canned response
+pub struct MockServer {
+    addr: std::net::SocketAddr,
+    in_flight: Arc<InFlight>,
+}
+
+/// Requests being answered, and the most answered at once
+#[derive(Default)]
+struct InFlight {
+    now: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl MockServer {
+    /// Serve `responses` in order, repeating the last one once exhausted
+    pub async fn start(responses: Vec<MockResponse>) -> Self {
+        let n = AtomicUsize::new(0);
+        Self::with_handler(move |_| {
+            let i = n.fetch_add(1, Ordering::SeqCst);
+            responses[i.min(responses.len() - 1)].clone()
+        })
+        .await
+    }
+
+    /// Answer every request with `resp`, recording the requests received
+    pub async fn recording(resp: MockResponse) -> (Self, Arc<Mutex<Vec<MockRequest>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let server = Self::with_handler(move |req| {
+            log.lock().unwrap().push(req.clone());
+            resp.clone()
+        })
+        .await;
+        (server, seen)
+    }
+
+    /// Answer each request with `handler(request)`
+    pub async fn with_handler<F>(handler: F) -> Self
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler: Arc<Handler> = Arc::new(handler);
+        let in_flight = Arc::new(InFlight::default());
+        let counter = in_flight.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, handler.clone(), counter.clone()));
+            }
+        });
+        Self { addr, in_flight }
+    }
+
+    /// Most requests that were being answered at the same time
+    pub fn peak_in_flight(&self) -> usize {
+        self.in_flight.peak.load(Ordering::SeqCst)
+    }
+
+    /// Absolute URL for `path` on this server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+}
+
+async fn handle(mut stream: TcpStream, handler: Arc<Handler>, in_flight: Arc<InFlight>) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    let mut req = MockRequest {
+        method,
+        target,
+        headers,
+        body: String::new(),
+    };
+    let len: usize = req
+        .header("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    while buf.len() < head_end + len {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+    req.body = String::from_utf8_lossy(&buf[head_end..]).to_string();
+
+    let now = in_flight.now.fetch_add(1, Ordering::SeqCst) + 1;
+    in_flight.peak.fetch_max(now, Ordering::SeqCst);
+    let resp = handler(&req);
+    if let Some(delay) = resp.delay {
+        tokio::time::sleep(delay).await;
+    }
+    let mut out = format!("HTTP/1.1 {} Mock\r\n", resp.status);
+    for (k, v) in &resp.headers {
+        out.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    out.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        resp.body.len()
+    ));
+    let _ = stream.write_all(out.as_bytes()).await;
+    let _ = stream.write_all(&resp.body).await;
+    let _ = stream.shutdown().await;
+    in_flight.now.fetch_sub(1, Ordering::SeqCst);
+}
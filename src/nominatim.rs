@@ -13,6 +13,7 @@
 //!     let c: Config = Config {
 //!         url: "https://nominatim.openstreetmap.org/search".to_string(),
 //!         timeout: 25,
+//!         ..Default::default()
 //!     };
 //!
 //!     let g = Geocode {
@@ -23,11 +24,12 @@
 //!         state: None,
 //!         country: None,
 //!         postalcode: None,
+//!         ..Default::default()
 //!     };
 //!
-//!     let resp = g.search(&c).await.unwrap();
-//!     assert_eq!(resp[0].lat, 42.3554334);
-//!     assert_eq!(resp[0].lon, -71.060511);
+//!     let resp = g.search_one(&c).await.unwrap();
+//!     assert_eq!(resp.lat, 42.3554334);
+//!     assert_eq!(resp.lon, -71.060511);
 //! }
 //! ```
 //! # Reverse geocode
@@ -38,35 +40,139 @@
 //!    let c: Config = Config {
 //!        url: "https://nominatim.openstreetmap.org/reverse".to_string(),
 //!        timeout: 25,
+//!        ..Default::default()
 //!    };
 //!
 //!    let g = ReverseGeocode {
 //!        lat: 42.3554334,
 //!        lon: -71.060511,
+//!        ..Default::default()
 //!    };
 //!
 //!    let resp = g.search(&c).await.unwrap();
 //!    assert_eq!(resp.osm_id, 5331978048);
 //! }
 //! ```
-use crate::overpass::BoundingBox;
-use reqwest;
-use serde::Deserialize;
+use crate::http;
+use crate::overpass::{self, BoundingBox, Coordinate};
+use crate::ratelimit::RateLimiter;
+use crate::retry::{self, RetryPolicy};
+use crate::Error;
+use futures_util::stream::{Stream, StreamExt};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use serde_aux::prelude::deserialize_number_from_string;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
-/// User agent string
-static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+/// Search parameters without a typed field that may be passed through with
+/// [`Geocode::with_param`]
+const PASSTHROUGH_PARAMS: &[&str] = &[
+    "accept-language",
+    "bounded",
+    "countrycodes",
+    "layer",
+    "limit",
+    "polygon_kml",
+    "polygon_svg",
+    "polygon_text",
+    "viewbox",
+];
+
+/// `polygon_*` output formats, one of which `polygon_threshold` requires
+const POLYGON_PARAMS: &[&str] = &["polygon_kml", "polygon_svg", "polygon_text"];
+
+/// Coarsest `place_rank` below which a result is at most county level
+const BROAD_RANK: u64 = 12;
+
+/// Address types at or above county level
+const BROAD_ADDRESSTYPES: &[&str] = &[
+    "continent",
+    "country",
+    "state",
+    "region",
+    "province",
+    "county",
+];
 
 /// Query configuration
-#[derive(Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub url: String,
+    /// Request timeout in seconds, 0 to wait indefinitely
     pub timeout: u8,
+    /// Minimum delay between requests issued by batch helpers such as
+    /// [`ReverseGeocode::stream`]. The public instance allows one per second.
+    pub rate_limit: Option<Duration>,
+    /// Contact address sent as the `email` parameter, as recommended by the
+    /// Nominatim usage policy for bulk users
+    pub email: Option<String>,
+    /// User agent identifying the application, defaults to `osm-rs/<version>`
+    pub user_agent: Option<String>,
+    /// Fixed pause after each request issued by batch helpers, for use
+    /// instead of or on top of `rate_limit`
+    pub delay_between: Option<Duration>,
+    /// Redirects to follow before giving up, `Some(0)` to follow none
+    pub max_redirects: Option<usize>,
+    /// Throttle shared by every clone of this config and awaited before each
+    /// request, see [`Config::with_shared_limiter`]. Batch helpers then no
+    /// longer apply `rate_limit` on their own.
+    #[serde(skip)]
+    pub limiter: Option<Arc<RateLimiter>>,
+    /// Client to send requests with instead of building one per request,
+    /// see [`Config::with_client`]
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+}
+
+impl Config {
+    /// Copy of this config sending every request, including those of its
+    /// clones, through `limiter`
+    pub fn with_shared_limiter(&self, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            limiter: Some(limiter),
+            ..self.clone()
+        }
+    }
+
+    /// Copy of this config sending requests with `client`, reusing its
+    /// connections. The client's own user agent, redirect policy and
+    /// default headers apply in place of those of the config and the
+    /// [`EndpointRegistry`](crate::registry::EndpointRegistry).
+    pub fn with_client(&self, client: reqwest::Client) -> Self {
+        Self {
+            client: Some(client),
+            ..self.clone()
+        }
+    }
+
+    /// Request timeout from `timeout` in seconds, none when it is 0
+    fn http_timeout(&self) -> Option<Duration> {
+        (self.timeout > 0).then(|| Duration::from_secs(self.timeout as u64))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url: "https://nominatim.openstreetmap.org/search".to_string(),
+            timeout: 25,
+            rate_limit: Some(Duration::from_secs(1)),
+            email: None,
+            user_agent: None,
+            delay_between: None,
+            max_redirects: None,
+            limiter: None,
+            client: None,
+        }
+    }
 }
 
 /// Defines a search query
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Geocode {
     pub q: Option<String>,
     pub street: Option<String>,
@@ -75,15 +181,150 @@ pub struct Geocode {
     pub state: Option<String>,
     pub country: Option<String>,
     pub postalcode: Option<String>,
+    /// Request the address broken down into its parts
+    #[serde(default)]
+    pub addressdetails: bool,
+    /// Request additional tags such as `wikidata` or `opening_hours`
+    #[serde(default)]
+    pub extratags: bool,
+    /// Request the place name in every available language
+    #[serde(default)]
+    pub namedetails: bool,
+    /// Request the outline of the place as GeoJSON
+    #[serde(default)]
+    pub polygon_geojson: bool,
+    /// Simplify requested outlines to this tolerance in degrees, e.g. `0.001`
+    #[serde(default)]
+    pub polygon_threshold: Option<f64>,
+    /// Merge near-duplicate results, Nominatim's default when `None`
+    #[serde(default)]
+    pub dedupe: Option<bool>,
+    /// Only return places of this kind
+    #[serde(default)]
+    pub feature_type: Option<FeatureType>,
+    /// Skip these `place_id`s, e.g. those already shown, to page through
+    /// results
+    #[serde(default)]
+    pub exclude_place_ids: Option<Vec<u64>>,
+    /// Other Nominatim parameters, see [`Geocode::with_param`]
+    #[serde(default)]
+    pub extra_params: BTreeMap<String, String>,
+}
+
+/// Response format requested by [`Geocode::search_as`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NominatimFormat {
+    Json,
+    GeoJson,
+}
+
+impl NominatimFormat {
+    /// Value of the `format` parameter
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NominatimFormat::Json => "json",
+            NominatimFormat::GeoJson => "geojson",
+        }
+    }
+}
+
+/// Results of [`Geocode::search_as`] in the requested format
+#[derive(Debug)]
+pub enum GeocodeResults {
+    Json(Vec<GeocodeResponse>),
+    /// A GeoJSON `FeatureCollection`
+    GeoJson(serde_json::Value),
+}
+
+/// Kind of place a [`Geocode`] search is restricted to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeatureType {
+    Country,
+    State,
+    City,
+    /// Any of state, city, town or village
+    Settlement,
+}
+
+impl FeatureType {
+    /// Value of the `featureType` parameter
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeatureType::Country => "country",
+            FeatureType::State => "state",
+            FeatureType::City => "city",
+            FeatureType::Settlement => "settlement",
+        }
+    }
+}
+
+/// Builds a [`Geocode`] query field by field
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::nominatim::Geocode;
+///
+/// let g = Geocode::builder()
+///     .street("10 Downing St")
+///     .city("London")
+///     .country("UK")
+///     .build()
+///     .unwrap();
+/// assert_eq!(g.city.as_deref(), Some("London"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GeocodeBuilder {
+    inner: Geocode,
 }
 
 /// Defines a reverse geocode query
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ReverseGeocode {
     pub lon: f64,
     pub lat: f64,
+    /// Level of detail between 0 and 18, defaults to 18 (building) when unset:
+    ///
+    /// | zoom | detail |
+    /// |------|--------|
+    /// | 3 | country |
+    /// | 5 | state |
+    /// | 8 | county |
+    /// | 10 | city |
+    /// | 12 | town / borough |
+    /// | 13 | village / suburb |
+    /// | 14 | neighbourhood |
+    /// | 15 | any settlement |
+    /// | 16 | major streets |
+    /// | 17 | major and minor streets |
+    /// | 18 | building |
+    #[serde(default)]
+    pub zoom: Option<u8>,
+    /// Request the address broken down into its parts. The public instance
+    /// includes it for reverse lookups even when unset.
+    #[serde(default)]
+    pub addressdetails: bool,
+    /// Request additional tags such as `wikidata` or `opening_hours`
+    #[serde(default)]
+    pub extratags: bool,
+    /// Request the place name in every available language
+    #[serde(default)]
+    pub namedetails: bool,
+    /// Comma-separated kinds of feature to consider, e.g. `address,poi`
+    #[serde(default)]
+    pub layer: Option<String>,
 }
 
+/// Most detailed reverse geocoding zoom level
+const MAX_ZOOM: u8 = 18;
+
+/// Results less important than this are dropped by [`Geocode::best_guesses`]
+const MIN_BEST_GUESS_IMPORTANCE: f64 = 0.05;
+
+/// Most results Nominatim returns for one search
+const MAX_LIMIT: usize = 40;
+
 /// Payload returned by the Nominatim API
 #[derive(Debug, Deserialize)]
 pub struct GeocodeResponse {
@@ -104,20 +345,258 @@ pub struct GeocodeResponse {
     pub addresstype: String,
     pub name: String,
     pub display_name: String,
+    /// Raw bounding box in Nominatim's `[minlat, maxlat, minlon, maxlon]` order.
+    /// Use [`GeocodeResponse::bbox`] for a correctly oriented box.
     pub boundingbox: BoundingBox,
+    /// Address parts such as `road`, `city` or `country_code`, present when
+    /// requested with [`Geocode::addressdetails`] or
+    /// [`ReverseGeocode::addressdetails`]
+    #[serde(default)]
+    pub address: Option<HashMap<String, String>>,
+    /// Present when requested with [`Geocode::extratags`]
+    #[serde(default)]
+    pub extratags: Option<HashMap<String, String>>,
+    /// Present when requested with [`Geocode::namedetails`]
+    #[serde(default)]
+    pub namedetails: Option<HashMap<String, String>>,
+    /// GeoJSON geometry, present when requested with [`Geocode::polygon_geojson`]
+    #[serde(default)]
+    pub geojson: Option<serde_json::Value>,
+}
+
+impl GeocodeResponse {
+    /// ISO 3166-1 alpha-2 country code from the address details, e.g. `"us"`
+    pub fn country_code(&self) -> Option<&str> {
+        self.address
+            .as_ref()?
+            .get("country_code")
+            .map(String::as_str)
+    }
+
+    /// Bounding box of the result with longitude in `x` and latitude in `y`
+    pub fn bbox(&self) -> BoundingBox {
+        BoundingBox {
+            xmin: self.boundingbox.xmax,
+            ymin: self.boundingbox.xmin,
+            xmax: self.boundingbox.ymax,
+            ymax: self.boundingbox.ymin,
+        }
+    }
+
+    /// Human label for the result's `place_rank`, see [`rank_label`]
+    pub fn rank_label(&self) -> &'static str {
+        rank_label(self.place_rank)
+    }
+
+    /// Whether the result is county level or coarser, as Nominatim tends to
+    /// return when it can't match the exact query
+    pub fn is_broad_match(&self) -> bool {
+        self.place_rank <= BROAD_RANK || BROAD_ADDRESSTYPES.contains(&self.addresstype.as_str())
+    }
+
+    /// Whether the result is coarser than the finest field of `query`,
+    /// e.g. a country for a street query. Falls back to
+    /// [`GeocodeResponse::is_broad_match`] for free-form queries.
+    pub fn is_broad_match_for(&self, query: &Geocode) -> bool {
+        // Minimum place_rank expected for each structured field
+        let expected = [
+            (&query.street, 26),
+            (&query.postalcode, 21),
+            (&query.city, 13),
+            (&query.county, 10),
+            (&query.state, 5),
+            (&query.country, 4),
+        ]
+        .into_iter()
+        .find(|(field, _)| field.is_some())
+        .map(|(_, rank)| rank);
+
+        match expected {
+            Some(rank) => self.place_rank < rank,
+            None => self.is_broad_match(),
+        }
+    }
+
+    /// Whether the result is of `class`, e.g. `boundary`, and of
+    /// `place_type` within it when given, e.g. `administrative`
+    pub fn is_class(&self, class: &str, place_type: Option<&str>) -> bool {
+        self.class == class && place_type.is_none_or(|t| self.place_type == t)
+    }
+
+    /// Whether the result is an administrative boundary such as a city,
+    /// state or country
+    pub fn is_administrative(&self) -> bool {
+        self.is_class("boundary", Some("administrative"))
+    }
+
+    /// Components of `display_name`, split on commas and trimmed
+    pub fn display_name_parts(&self) -> Vec<&str> {
+        self.display_name
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .collect()
+    }
+
+    /// Build an Overpass config and bounding box to search for `key=val` within this result
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::nominatim::{Config, Geocode};
+    /// use osm_rs::overpass::Search;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let c: Config = Config {
+    ///         url: "https://nominatim.openstreetmap.org/search".to_string(),
+    ///         timeout: 25,
+    ///         ..Default::default()
+    ///     };
+    ///
+    ///     let g = Geocode::new("Cambridge, MA".to_string());
+    ///     let resp = g.search_one(&c).await.unwrap();
+    ///     let (oc, b) = resp.overpass_config(
+    ///         "amenity",
+    ///         "cafe",
+    ///         "https://overpass-api.de/api/interpreter",
+    ///     );
+    ///     let cafes = b.search(&oc).await.unwrap();
+    /// }
+    /// ```
+    pub fn overpass_config(
+        &self,
+        key: &str,
+        val: &str,
+        url: &str,
+    ) -> (overpass::Config, BoundingBox) {
+        let config = overpass::Config {
+            url: url.to_string(),
+            key: key.to_string(),
+            val: val.to_string(),
+            ..Default::default()
+        };
+        (config, self.bbox())
+    }
+}
+
+/// Human label for a Nominatim `place_rank`, e.g. `"country"` for 4
+pub fn rank_label(rank: u64) -> &'static str {
+    match rank {
+        0..=3 => "continent",
+        4 => "country",
+        5..=9 => "state",
+        10..=12 => "county",
+        13..=16 => "city",
+        17..=21 => "town",
+        22..=24 => "neighbourhood",
+        25 => "locality",
+        26..=27 => "street",
+        _ => "address",
+    }
+}
+
+/// Helpers over a list of geocoding results
+pub trait GeocodeResponsesExt {
+    /// Results keyed by `place_rank`, from the broadest to the most detailed
+    fn group_by_rank(&self) -> BTreeMap<u64, Vec<&GeocodeResponse>>;
+
+    /// Number of results per country code, skipping results without one.
+    /// Requires [`Geocode::addressdetails`].
+    fn count_by_country(&self) -> HashMap<String, usize>;
+
+    /// Results of `class` and, when given, `place_type`, see
+    /// [`GeocodeResponse::is_class`]
+    fn filter_class(&self, class: &str, place_type: Option<&str>) -> Vec<&GeocodeResponse>;
+}
+
+impl GeocodeResponsesExt for [GeocodeResponse] {
+    fn group_by_rank(&self) -> BTreeMap<u64, Vec<&GeocodeResponse>> {
+        let mut groups: BTreeMap<u64, Vec<&GeocodeResponse>> = BTreeMap::new();
+        for r in self {
+            groups.entry(r.place_rank).or_default().push(r);
+        }
+        groups
+    }
+
+    fn count_by_country(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for code in self.iter().filter_map(GeocodeResponse::country_code) {
+            *counts.entry(code.to_string()).or_default() += 1;
+        }
+        counts
+    }
+
+    fn filter_class(&self, class: &str, place_type: Option<&str>) -> Vec<&GeocodeResponse> {
+        self.iter()
+            .filter(|r| r.is_class(class, place_type))
+            .collect()
+    }
 }
 
 impl Geocode {
     pub fn new(s: String) -> Self {
         Self {
             q: Some(s),
-            street: None,
-            city: None,
-            county: None,
-            state: None,
-            country: None,
-            postalcode: None,
+            ..Default::default()
+        }
+    }
+
+    /// Start building a query
+    pub fn builder() -> GeocodeBuilder {
+        GeocodeBuilder::default()
+    }
+
+    /// Whether any structured field is set
+    fn has_structured(&self) -> bool {
+        self.street.is_some()
+            || self.city.is_some()
+            || self.county.is_some()
+            || self.state.is_some()
+            || self.country.is_some()
+            || self.postalcode.is_some()
+    }
+
+    fn has_polygon(&self) -> bool {
+        self.polygon_geojson
+            || POLYGON_PARAMS
+                .iter()
+                .any(|p| self.extra_params.get(*p).is_some_and(|v| v != "0"))
+    }
+
+    /// Check that the query names a place one way. Nominatim does not
+    /// accept `q` together with structured fields, and `to_params` would
+    /// drop the structured ones, so setting both is an error, as is setting
+    /// neither. A `polygon_threshold` must be non-negative and come with a
+    /// polygon output format.
+    pub fn validate(&self) -> Result<(), Error> {
+        match (self.q.is_some(), self.has_structured()) {
+            (true, true) => {
+                return Err(Error::InvalidQuery(
+                    "q cannot be combined with structured fields".to_string(),
+                ))
+            }
+            (false, false) => {
+                return Err(Error::InvalidQuery(
+                    "either q or a structured field is required".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        if let Some(t) = self.polygon_threshold {
+            if !(t.is_finite() && t >= 0.0) {
+                return Err(Error::InvalidQuery(format!(
+                    "polygon_threshold must be non-negative, got {}",
+                    t
+                )));
+            }
+            if !self.has_polygon() {
+                return Err(Error::InvalidQuery(
+                    "polygon_threshold requires a polygon output format".to_string(),
+                ));
+            }
         }
+        Ok(())
     }
 
     /// Asynchronously search by location.
@@ -132,6 +611,7 @@ impl Geocode {
     ///     let c: Config = Config {
     ///         url: "https://nominatim.openstreetmap.org/search".to_string(),
     ///         timeout: 25,
+    ///         ..Default::default()
     ///     };
     ///
     ///     let g = Geocode::new("Boston".to_string());
@@ -140,47 +620,312 @@ impl Geocode {
     ///     assert_eq!(resp[0].lon, -71.060511);
     /// }
     /// ```
-    pub async fn search(&self, config: &Config) -> Result<Vec<GeocodeResponse>, reqwest::Error> {
-        let client = reqwest::Client::builder()
-            .user_agent(APP_USER_AGENT)
-            .build()?;
-        let params = self.to_params();
-        let url = format!("{}?format=json", config.url);
-        let res = client.get(url).query(&params).send().await?;
-        let resp: Vec<GeocodeResponse> = res.json().await?;
+    pub async fn search(&self, config: &Config) -> Result<Vec<GeocodeResponse>, Error> {
+        let res = self.send(config, NominatimFormat::Json).await?;
+        let resp: Vec<GeocodeResponse> = http::json(res).await?;
         Ok(resp)
     }
 
+    /// Search as with `search`, retrying throttled and failed requests
+    /// according to `policy`
+    pub async fn search_with_retry(
+        &self,
+        config: &Config,
+        policy: &RetryPolicy,
+    ) -> Result<Vec<GeocodeResponse>, Error> {
+        retry::with_retry(policy, || self.search(config)).await
+    }
+
+    /// Run the query, also returning the final request URL, after any
+    /// redirects, for audit logs
+    pub async fn search_audited(
+        &self,
+        config: &Config,
+    ) -> Result<(Vec<GeocodeResponse>, Url), Error> {
+        let res = self.send(config, NominatimFormat::Json).await?;
+        let url = res.url().clone();
+        let resp: Vec<GeocodeResponse> = http::json(res).await?;
+        Ok((resp, url))
+    }
+
+    /// Best match for the query, or [`Error::Empty`] when nothing matched
+    pub async fn search_one(&self, config: &Config) -> Result<GeocodeResponse, Error> {
+        self.search(config)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(Error::Empty)
+    }
+
+    /// Up to `max` distinct candidates for a picker, most important first.
+    /// Asks for twice as many results with address details, keeps the first
+    /// result for each OSM element and drops those with an importance below
+    /// 0.05.
+    pub async fn best_guesses(
+        &self,
+        config: &Config,
+        max: usize,
+    ) -> Result<Vec<GeocodeResponse>, Error> {
+        let mut g = self.clone();
+        g.addressdetails = true;
+        g.extra_params.insert(
+            "limit".to_string(),
+            (max * 2).clamp(1, MAX_LIMIT).to_string(),
+        );
+        let mut seen = HashSet::new();
+        let mut guesses: Vec<GeocodeResponse> = g
+            .search(config)
+            .await?
+            .into_iter()
+            .filter(|r| r.importance >= MIN_BEST_GUESS_IMPORTANCE)
+            .filter(|r| seen.insert((r.osm_type.clone(), r.osm_id)))
+            .collect();
+        guesses.sort_by(|a, b| b.importance.total_cmp(&a.importance));
+        guesses.truncate(max);
+        Ok(guesses)
+    }
+
+    /// Run the query requesting `format`, e.g. GeoJSON for mapping and plain
+    /// JSON for data from the same config
+    pub async fn search_as(
+        &self,
+        config: &Config,
+        format: NominatimFormat,
+    ) -> Result<GeocodeResults, Error> {
+        let res = self.send(config, format).await?;
+        Ok(match format {
+            NominatimFormat::Json => GeocodeResults::Json(http::json(res).await?),
+            NominatimFormat::GeoJson => GeocodeResults::GeoJson(http::json(res).await?),
+        })
+    }
+
+    async fn send(
+        &self,
+        config: &Config,
+        format: NominatimFormat,
+    ) -> Result<reqwest::Response, Error> {
+        self.validate()?;
+        get(config, format, self.to_params()).await
+    }
+
+    /// Pass `key=value` through to Nominatim. Only keys in a known-safe
+    /// allowlist without a typed field are accepted, e.g. `countrycodes`,
+    /// besides `exclude_place_ids`, whose comma-separated ids are merged
+    /// into [`Geocode::exclude_place_ids`], and `polygon_threshold`, which
+    /// sets [`Geocode::polygon_threshold`].
+    pub fn with_param(&mut self, key: &str, value: impl Into<String>) -> Result<&mut Self, Error> {
+        let value = value.into();
+        match key {
+            "exclude_place_ids" => {
+                let ids = value
+                    .split(',')
+                    .map(|id| id.trim().parse::<u64>())
+                    .collect::<Result<Vec<u64>, _>>()
+                    .map_err(|_| Error::InvalidQuery(format!("invalid place ids `{}`", value)))?;
+                let excluded = self.exclude_place_ids.get_or_insert_with(Vec::new);
+                for id in ids {
+                    if !excluded.contains(&id) {
+                        excluded.push(id);
+                    }
+                }
+                return Ok(self);
+            }
+            "polygon_threshold" => {
+                let t = value.trim().parse::<f64>().map_err(|_| {
+                    Error::InvalidQuery(format!("invalid polygon_threshold `{}`", value))
+                })?;
+                self.polygon_threshold = Some(t);
+                return Ok(self);
+            }
+            _ => {}
+        }
+        if !PASSTHROUGH_PARAMS.contains(&key) {
+            return Err(Error::InvalidQuery(format!(
+                "unsupported parameter `{}`",
+                key
+            )));
+        }
+        if value.chars().any(char::is_control) {
+            return Err(Error::InvalidQuery(format!(
+                "control character in value of `{}`",
+                key
+            )));
+        }
+        self.extra_params.insert(key.to_string(), value);
+        Ok(self)
+    }
+
     /// Construct GET request params
-    pub fn to_params(&self) -> HashMap<&str, &String> {
-        let mut params = HashMap::new();
+    pub fn to_params(&self) -> HashMap<&str, String> {
+        let mut params: HashMap<&str, String> = self
+            .extra_params
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone()))
+            .collect();
         if let Some(q) = &self.q {
-            params.insert("q", q);
+            params.insert("q", q.clone());
         } else {
-            if let Some(street) = &self.street {
-                params.insert("street", street);
-            }
-            if let Some(city) = &self.city {
-                params.insert("city", city);
-            }
-            if let Some(county) = &self.county {
-                params.insert("county", county);
-            }
-            if let Some(state) = &self.state {
-                params.insert("state", state);
-            }
-            if let Some(country) = &self.country {
-                params.insert("country", country);
-            }
-            if let Some(postalcode) = &self.postalcode {
-                params.insert("postalcode", postalcode);
+            let fields = [
+                ("street", &self.street),
+                ("city", &self.city),
+                ("county", &self.county),
+                ("state", &self.state),
+                ("country", &self.country),
+                ("postalcode", &self.postalcode),
+            ];
+            for (key, val) in fields {
+                if let Some(val) = val {
+                    params.insert(key, val.clone());
+                }
             }
         }
+        if self.addressdetails {
+            params.insert("addressdetails", "1".to_string());
+        }
+        if self.extratags {
+            params.insert("extratags", "1".to_string());
+        }
+        if self.namedetails {
+            params.insert("namedetails", "1".to_string());
+        }
+        if self.polygon_geojson {
+            params.insert("polygon_geojson", "1".to_string());
+        }
+        if let Some(t) = self.polygon_threshold {
+            params.insert("polygon_threshold", t.to_string());
+        }
+        if let Some(dedupe) = self.dedupe {
+            params.insert("dedupe", if dedupe { "1" } else { "0" }.to_string());
+        }
+        if let Some(feature_type) = self.feature_type {
+            params.insert("featureType", feature_type.as_str().to_string());
+        }
+        if let Some(ids) = &self.exclude_place_ids {
+            let ids: Vec<String> = ids.iter().map(u64::to_string).collect();
+            params.insert("exclude_place_ids", ids.join(","));
+        }
         params
     }
 }
 
+impl GeocodeBuilder {
+    /// Free-form query, mutually exclusive with the structured fields
+    pub fn q(mut self, q: impl Into<String>) -> Self {
+        self.inner.q = Some(q.into());
+        self
+    }
+
+    pub fn street(mut self, street: impl Into<String>) -> Self {
+        self.inner.street = Some(street.into());
+        self
+    }
+
+    pub fn city(mut self, city: impl Into<String>) -> Self {
+        self.inner.city = Some(city.into());
+        self
+    }
+
+    pub fn county(mut self, county: impl Into<String>) -> Self {
+        self.inner.county = Some(county.into());
+        self
+    }
+
+    pub fn state(mut self, state: impl Into<String>) -> Self {
+        self.inner.state = Some(state.into());
+        self
+    }
+
+    pub fn country(mut self, country: impl Into<String>) -> Self {
+        self.inner.country = Some(country.into());
+        self
+    }
+
+    pub fn postalcode(mut self, postalcode: impl Into<String>) -> Self {
+        self.inner.postalcode = Some(postalcode.into());
+        self
+    }
+
+    pub fn addressdetails(mut self, addressdetails: bool) -> Self {
+        self.inner.addressdetails = addressdetails;
+        self
+    }
+
+    pub fn extratags(mut self, extratags: bool) -> Self {
+        self.inner.extratags = extratags;
+        self
+    }
+
+    pub fn namedetails(mut self, namedetails: bool) -> Self {
+        self.inner.namedetails = namedetails;
+        self
+    }
+
+    pub fn polygon_geojson(mut self, polygon_geojson: bool) -> Self {
+        self.inner.polygon_geojson = polygon_geojson;
+        self
+    }
+
+    pub fn polygon_threshold(mut self, polygon_threshold: f64) -> Self {
+        self.inner.polygon_threshold = Some(polygon_threshold);
+        self
+    }
+
+    pub fn dedupe(mut self, dedupe: bool) -> Self {
+        self.inner.dedupe = Some(dedupe);
+        self
+    }
+
+    pub fn feature_type(mut self, feature_type: FeatureType) -> Self {
+        self.inner.feature_type = Some(feature_type);
+        self
+    }
+
+    pub fn exclude_place_ids(mut self, ids: Vec<u64>) -> Self {
+        self.inner.exclude_place_ids = Some(ids);
+        self
+    }
+
+    /// Build the query, see [`Geocode::validate`]
+    pub fn build(self) -> Result<Geocode, Error> {
+        self.inner.validate()?;
+        Ok(self.inner)
+    }
+}
+
+impl TryFrom<&str> for Geocode {
+    type Error = Error;
+
+    /// Parse a structured query such as `street=10 Downing St;city=London;country=UK`
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let mut g = Geocode::default();
+        for pair in s.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, val) = pair.split_once('=').ok_or_else(|| {
+                Error::InvalidQuery(format!("expected key=value, got `{}`", pair))
+            })?;
+            let val = Some(val.trim().to_string());
+            match key.trim() {
+                "street" => g.street = val,
+                "city" => g.city = val,
+                "county" => g.county = val,
+                "state" => g.state = val,
+                "country" => g.country = val,
+                "postalcode" => g.postalcode = val,
+                k => return Err(Error::InvalidQuery(format!("unknown field `{}`", k))),
+            }
+        }
+        Ok(g)
+    }
+}
+
 impl ReverseGeocode {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self {
+            lat,
+            lon,
+            ..Default::default()
+        }
+    }
+
     /// Asynchronously reverse geocode
     /// # Example
     /// ```rust
@@ -190,49 +935,1438 @@ impl ReverseGeocode {
     ///    let c: Config = Config {
     ///        url: "https://nominatim.openstreetmap.org/reverse".to_string(),
     ///        timeout: 25,
+    ///        ..Default::default()
     ///    };
     ///
     ///    let g = ReverseGeocode {
     ///        lat: 42.3554334,
     ///        lon: -71.060511,
+    ///        ..Default::default()
     ///    };
     ///
     ///    let resp = g.search(&c).await.unwrap();
     ///    assert_eq!(resp.osm_id, 5331978048);
     /// }
     /// ```
-    pub async fn search(&self, config: &Config) -> Result<GeocodeResponse, reqwest::Error> {
-        let client = reqwest::Client::builder()
-            .user_agent(APP_USER_AGENT)
-            .build()?;
+    pub async fn search(&self, config: &Config) -> Result<GeocodeResponse, Error> {
+        let res = get(config, NominatimFormat::Json, self.to_params()?).await?;
+        reverse_response(http::json(res).await?)
+    }
+
+    /// Reverse geocode as with `search`, retrying throttled and failed
+    /// requests according to `policy`
+    pub async fn search_with_retry(
+        &self,
+        config: &Config,
+        policy: &RetryPolicy,
+    ) -> Result<GeocodeResponse, Error> {
+        retry::with_retry(policy, || self.search(config)).await
+    }
+
+    /// Wikidata id, e.g. `Q100`, of the feature found at the location, from
+    /// its `wikidata` extra tag. `None` when the feature has no such tag.
+    pub async fn wikidata_id(&self, config: &Config) -> Result<Option<String>, Error> {
+        let mut params = self.to_params()?;
+        params.insert("extratags", "1".to_string());
+        let res = get(config, NominatimFormat::Json, params).await?;
+        let resp = reverse_response(http::json(res).await?)?;
+        Ok(resp.extratags.and_then(|mut tags| tags.remove("wikidata")))
+    }
 
+    /// Construct GET request params
+    pub fn to_params(&self) -> Result<HashMap<&str, String>, Error> {
         let mut params = HashMap::new();
         params.insert("lat", self.lat.to_string());
         params.insert("lon", self.lon.to_string());
+        if let Some(zoom) = self.zoom {
+            if zoom > MAX_ZOOM {
+                return Err(Error::InvalidQuery(format!(
+                    "zoom {} outside 0-{}",
+                    zoom, MAX_ZOOM
+                )));
+            }
+            params.insert("zoom", zoom.to_string());
+        }
+        if self.addressdetails {
+            params.insert("addressdetails", "1".to_string());
+        }
+        if self.extratags {
+            params.insert("extratags", "1".to_string());
+        }
+        if self.namedetails {
+            params.insert("namedetails", "1".to_string());
+        }
+        if let Some(layer) = &self.layer {
+            params.insert("layer", layer.clone());
+        }
+        Ok(params)
+    }
 
-        let url = format!("{}?format=json", config.url);
-        let res = client.get(url).query(&params).send().await?;
-        let resp: GeocodeResponse = res.json().await?;
-        Ok(resp)
+    /// Reverse geocode a stream of coordinates with at most `concurrency`
+    /// requests in flight, spaced by `config.rate_limit` and followed by
+    /// `config.delay_between`. Results are yielded in input order.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use futures_util::stream::{self, StreamExt};
+    /// use osm_rs::nominatim::{Config, ReverseGeocode};
+    /// use osm_rs::overpass::Coordinate;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let c = Config {
+    ///         url: "https://nominatim.openstreetmap.org/reverse".to_string(),
+    ///         ..Default::default()
+    ///     };
+    ///     let points = stream::iter(vec![
+    ///         Coordinate { lat: 42.3554334, lon: -71.060511 },
+    ///         Coordinate { lat: 51.5072, lon: -0.1276 },
+    ///     ]);
+    ///
+    ///     let results: Vec<_> = ReverseGeocode::stream(points, &c, 2).collect().await;
+    ///     assert_eq!(results.len(), 2);
+    /// }
+    /// ```
+    pub fn stream<'a, S>(
+        input: S,
+        config: &'a Config,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<GeocodeResponse, Error>> + 'a
+    where
+        S: Stream<Item = Coordinate> + 'a,
+    {
+        let rate_limit = config.rate_limit.filter(|_| config.limiter.is_none());
+        let limiter = Arc::new(rate_limit.map(RateLimiter::new));
+        input
+            .map(move |c| {
+                let limiter = limiter.clone();
+                async move {
+                    if let Some(limiter) = limiter.as_ref() {
+                        limiter.wait().await;
+                    }
+                    let res = ReverseGeocode::from(c).search(config).await;
+                    if let Some(delay) = config.delay_between {
+                        tokio::time::sleep(delay).await;
+                    }
+                    res
+                }
+            })
+            .buffered(concurrency.max(1))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tokio;
+/// Same as [`GeocodeResponse::bbox`]
+impl From<&GeocodeResponse> for BoundingBox {
+    fn from(r: &GeocodeResponse) -> Self {
+        r.bbox()
+    }
+}
 
-    #[tokio::test]
-    async fn test_geocode() {
-        let c: Config = Config {
-            url: "https://nominatim.openstreetmap.org/search".to_string(),
-            timeout: 25,
+impl From<GeocodeResponse> for BoundingBox {
+    fn from(r: GeocodeResponse) -> Self {
+        r.bbox()
+    }
+}
+
+/// Parse a reverse geocoding result, turning the `{"error": ...}` body sent
+/// when nothing is found into [`Error::NotFound`]. Older versions send the
+/// message as a string, newer ones as `{"code": ..., "message": ...}`.
+fn reverse_response(value: serde_json::Value) -> Result<GeocodeResponse, Error> {
+    if let Some(error) = value.get("error") {
+        let message = match error {
+            serde_json::Value::String(m) => m.clone(),
+            e => e
+                .get("message")
+                .and_then(|m| m.as_str())
+                .map_or_else(|| e.to_string(), str::to_string),
         };
+        return Err(Error::NotFound(message));
+    }
+    Ok(serde_json::from_value(value)?)
+}
 
-        let g = Geocode::new("Boston".to_string());
-        let resp = g.search(&c).await.unwrap();
-        assert_eq!(resp[0].lat, 42.3554334);
-        assert_eq!(resp[0].lon, -71.060511);
+impl From<Coordinate> for ReverseGeocode {
+    fn from(c: Coordinate) -> Self {
+        Self::new(c.lat, c.lon)
+    }
+}
+
+/// Search-as-you-type over [`Geocode::search`]. Each call to
+/// [`Autocomplete::query`] supersedes the previous one: a pending query is
+/// cancelled, and a new one is only sent once no newer call has arrived
+/// within the debounce interval.
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::nominatim::{Autocomplete, Config};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let ac = Autocomplete::new(Config::default(), Duration::from_millis(300));
+///     // e.g. on each keystroke
+///     match ac.query("Bost").await {
+///         Ok(results) => println!("{} suggestions", results.len()),
+///         Err(osm_rs::Error::Cancelled) => {} // superseded by a newer keystroke
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Autocomplete {
+    config: Config,
+    debounce: Duration,
+    pending: Arc<std::sync::Mutex<CancellationToken>>,
+}
+
+impl Autocomplete {
+    pub fn new(config: Config, debounce: Duration) -> Self {
+        Self {
+            config,
+            debounce,
+            pending: Arc::new(std::sync::Mutex::new(CancellationToken::new())),
+        }
+    }
+
+    /// Geocode `text` after the debounce interval, failing with
+    /// [`Error::Cancelled`] when a newer query supersedes it first. Clones
+    /// share the pending query.
+    pub async fn query(&self, text: &str) -> Result<Vec<GeocodeResponse>, Error> {
+        let token = CancellationToken::new();
+        std::mem::replace(&mut *self.pending.lock().unwrap(), token.clone()).cancel();
+        let g = Geocode::new(text.to_string());
+        tokio::select! {
+            biased;
+            _ = token.cancelled() => Err(Error::Cancelled),
+            res = async {
+                tokio::time::sleep(self.debounce).await;
+                g.search(&self.config).await
+            } => res,
+        }
+    }
+}
+
+/// Looks up a single place on the `/details` endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Details {
+    /// Nominatim `place_id`, as found in [`GeocodeResponse::place_id`]
+    PlaceId(u64),
+    /// OSM element type and id
+    Osm(overpass::OsmType, u64),
+}
+
+/// Place returned by the `/details` endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct DetailsResponse {
+    pub place_id: u64,
+    pub parent_place_id: Option<u64>,
+    /// `N`, `W` or `R`
+    pub osm_type: String,
+    pub osm_id: u64,
+    pub category: String,
+    #[serde(rename = "type")]
+    pub place_type: String,
+    pub admin_level: Option<u64>,
+    pub localname: String,
+    #[serde(default)]
+    pub names: HashMap<String, String>,
+    pub country_code: Option<String>,
+    pub rank_address: u64,
+    pub rank_search: u64,
+    /// GeoJSON point
+    pub centroid: Option<serde_json::Value>,
+    /// Address hierarchy from the place itself up to its country
+    #[serde(default)]
+    pub address: Vec<DetailsAddress>,
+}
+
+/// Entry of the [`DetailsResponse::address`] hierarchy
+#[derive(Debug, Clone, Deserialize)]
+pub struct DetailsAddress {
+    pub localname: String,
+    pub place_id: Option<u64>,
+    pub osm_id: Option<u64>,
+    pub osm_type: Option<String>,
+    pub class: String,
+    #[serde(rename = "type")]
+    pub place_type: String,
+    pub admin_level: Option<u64>,
+    pub rank_address: u64,
+    /// Whether the entry is part of the postal address
+    pub isaddress: bool,
+}
+
+impl Details {
+    /// Asynchronously fetch the details of the place
+    /// # Example
+    /// ```rust
+    /// use osm_rs::nominatim::{Config, Details};
+    /// #[tokio::main]
+    /// async fn main() {
+    ///    let c: Config = Config {
+    ///        url: "https://nominatim.openstreetmap.org/details".to_string(),
+    ///        ..Default::default()
+    ///    };
+    ///
+    ///    let resp = Details::PlaceId(297593038).search(&c).await.unwrap();
+    ///    println!("{}", resp.breadcrumbs().join(" > "));
+    /// }
+    /// ```
+    pub async fn search(&self, config: &Config) -> Result<DetailsResponse, Error> {
+        let res = get(config, NominatimFormat::Json, self.to_params()).await?;
+        let resp: DetailsResponse = http::json(res).await?;
+        Ok(resp)
+    }
+
+    /// Construct GET request params
+    pub fn to_params(&self) -> HashMap<&str, String> {
+        let mut params = HashMap::new();
+        match self {
+            Details::PlaceId(id) => {
+                params.insert("place_id", id.to_string());
+            }
+            Details::Osm(osm_type, id) => {
+                let t = match osm_type {
+                    overpass::OsmType::Node => "N",
+                    overpass::OsmType::Way => "W",
+                    overpass::OsmType::Relation => "R",
+                };
+                params.insert("osmtype", t.to_string());
+                params.insert("osmid", id.to_string());
+            }
+        }
+        params.insert("addressdetails", "1".to_string());
+        params
+    }
+}
+
+impl DetailsResponse {
+    /// Names of the address levels from the broadest down, e.g.
+    /// `["United States", "Massachusetts", "Boston"]`
+    pub fn breadcrumbs(&self) -> Vec<&str> {
+        let mut levels: Vec<&DetailsAddress> = self
+            .address
+            .iter()
+            .filter(|a| a.isaddress && a.rank_address > 0 && a.place_type != "postcode")
+            .collect();
+        levels.sort_by_key(|a| a.rank_address);
+        levels.iter().map(|a| a.localname.as_str()).collect()
+    }
+}
+
+/// Reject URLs that look like an Overpass interpreter rather than Nominatim
+fn check_endpoint(url: &str) -> Result<(), Error> {
+    let url = url.to_lowercase();
+    if url.contains("interpreter") || url.contains("overpass") {
+        return Err(Error::LikelyWrongEndpoint(url));
+    }
+    Ok(())
+}
+
+/// Send a GET to `config.url` with `params`, plus the `format` and the
+/// contact `email` from the config
+async fn get(
+    config: &Config,
+    format: NominatimFormat,
+    mut params: HashMap<&str, String>,
+) -> Result<reqwest::Response, Error> {
+    check_endpoint(&config.url)?;
+    if let Some(limiter) = &config.limiter {
+        limiter.wait().await;
+    }
+    let client = match &config.client {
+        Some(client) => client.clone(),
+        None => http::client(
+            &config.url,
+            config.user_agent.as_deref(),
+            config.http_timeout(),
+            config.max_redirects,
+            true,
+        )?,
+    };
+    if let Some(email) = &config.email {
+        params.insert("email", email.clone());
+    }
+    let url = format!("{}?format={}", config.url, format.as_str());
+    let mut req = client.get(url).query(&params);
+    if let Some(timeout) = config.http_timeout() {
+        req = req.timeout(timeout);
+    }
+    let res = req.send().await?;
+    match http::unavailable(&res) {
+        Some(e) => Err(e),
+        None => Ok(res),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockRequest, MockResponse, MockServer};
+    use crate::overpass::Search;
+    use futures_util::stream;
+    use std::sync::Mutex;
+    use tokio;
+
+    /// Mock answering with `body` and recording every request
+    async fn recording_server(body: String) -> (MockServer, Arc<Mutex<Vec<MockRequest>>>) {
+        MockServer::recording(MockResponse::json(&body)).await
+    }
+
+    const BOSTON: &str = include_str!("../fixtures/nominatim_boston.json");
+
+    /// Nominatim result based on the Boston fixture, with setters for the
+    /// fields tests vary
+    #[derive(Clone)]
+    struct Place(serde_json::Value);
+
+    impl Place {
+        fn boston() -> Self {
+            Self(serde_json::from_str(BOSTON).unwrap())
+        }
+
+        fn place_id(self, place_id: u64) -> Self {
+            self.set("place_id", place_id)
+        }
+
+        fn osm_id(self, osm_id: u64) -> Self {
+            self.set("osm_id", osm_id)
+        }
+
+        fn importance(self, importance: f64) -> Self {
+            self.set("importance", importance)
+        }
+
+        fn name(self, name: &str) -> Self {
+            self.set("name", name)
+        }
+
+        fn display_name(self, display_name: &str) -> Self {
+            self.set("display_name", display_name)
+        }
+
+        fn class(self, class: &str, place_type: &str) -> Self {
+            self.set("class", class).set("type", place_type)
+        }
+
+        fn place_rank(self, place_rank: u64) -> Self {
+            self.set("place_rank", place_rank)
+        }
+
+        fn addresstype(self, addresstype: &str) -> Self {
+            self.set("addresstype", addresstype)
+        }
+
+        fn address(self, parts: &[(&str, &str)]) -> Self {
+            self.set("address", object(parts))
+        }
+
+        fn extratags(self, tags: &[(&str, &str)]) -> Self {
+            self.set("extratags", object(tags))
+        }
+
+        fn namedetails(self, names: &[(&str, &str)]) -> Self {
+            self.set("namedetails", object(names))
+        }
+
+        fn geojson(self, geojson: serde_json::Value) -> Self {
+            self.set("geojson", geojson)
+        }
+
+        fn set(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+            self.0[key] = value.into();
+            self
+        }
+
+        fn build(&self) -> GeocodeResponse {
+            serde_json::from_value(self.0.clone()).unwrap()
+        }
+
+        fn json(&self) -> String {
+            self.0.to_string()
+        }
+    }
+
+    /// Search response listing `places`
+    fn places(places: &[Place]) -> String {
+        serde_json::Value::Array(places.iter().map(|p| p.0.clone()).collect()).to_string()
+    }
+
+    fn object(pairs: &[(&str, &str)]) -> serde_json::Value {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), serde_json::Value::from(*v)))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_geocode() {
+        let c: Config = Config {
+            url: "https://nominatim.openstreetmap.org/search".to_string(),
+            timeout: 25,
+            ..Default::default()
+        };
+
+        let g = Geocode::new("Boston".to_string());
+        let resp = g.search(&c).await.unwrap();
+        assert_eq!(resp[0].lat, 42.3554334);
+        assert_eq!(resp[0].lon, -71.060511);
+    }
+
+    #[test]
+    fn test_geocode_try_from() {
+        let g = Geocode::try_from("street=10 Downing St; city=London;country=UK").unwrap();
+        assert_eq!(g.q, None);
+        assert_eq!(g.street.as_deref(), Some("10 Downing St"));
+        assert_eq!(g.city.as_deref(), Some("London"));
+        assert_eq!(g.country.as_deref(), Some("UK"));
+
+        assert!(matches!(
+            Geocode::try_from("street=10 Downing St;town=London"),
+            Err(Error::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            Geocode::try_from("London"),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_geocode_builder() {
+        let g = Geocode::builder()
+            .street("10 Downing St")
+            .city("London")
+            .postalcode("SW1A 2AA")
+            .build()
+            .unwrap();
+        assert_eq!(g.q, None);
+        assert_eq!(g.street.as_deref(), Some("10 Downing St"));
+        assert_eq!(g.postalcode.as_deref(), Some("SW1A 2AA"));
+
+        let g = Geocode::builder().q("Boston").build().unwrap();
+        assert_eq!(g.to_params().get("q").map(|q| q.as_str()), Some("Boston"));
+        assert_eq!(g.to_params().len(), 1);
+
+        assert!(matches!(
+            Geocode::builder().q("Boston").city("Boston").build(),
+            Err(Error::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            Geocode::builder().extratags(true).build(),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search_validates() {
+        let (server, seen) = recording_server(format!("[{}]", BOSTON)).await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let both = Geocode {
+            q: Some("Boston".to_string()),
+            city: Some("Boston".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(both.search(&c).await, Err(Error::InvalidQuery(_))));
+        assert!(matches!(
+            Geocode::default().search(&c).await,
+            Err(Error::InvalidQuery(_))
+        ));
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_display_name_parts() {
+        let r: GeocodeResponse = serde_json::from_str(BOSTON).unwrap();
+        assert_eq!(
+            r.display_name_parts(),
+            vec!["Boston", "Massachusetts", "United States"]
+        );
+    }
+
+    #[test]
+    fn test_extratags_namedetails() {
+        let g = Geocode::builder()
+            .q("Boston")
+            .extratags(true)
+            .namedetails(true)
+            .build()
+            .unwrap();
+        let params = g.to_params();
+        assert_eq!(params.get("extratags").map(|v| v.as_str()), Some("1"));
+        assert_eq!(params.get("namedetails").map(|v| v.as_str()), Some("1"));
+
+        let plain: GeocodeResponse = serde_json::from_str(BOSTON).unwrap();
+        assert!(plain.extratags.is_none() && plain.namedetails.is_none());
+
+        let r = Place::boston()
+            .extratags(&[("wikidata", "Q100"), ("population", "675647")])
+            .namedetails(&[("name", "Boston"), ("name:ja", "ボストン")])
+            .build();
+        assert_eq!(r.extratags.unwrap()["wikidata"], "Q100");
+        assert_eq!(r.namedetails.unwrap()["name:ja"], "ボストン");
+    }
+
+    #[tokio::test]
+    async fn test_search_as() {
+        let server = MockServer::with_handler(|req| match req.query("format") {
+            Some("geojson") => MockResponse::json(
+                r#"{"type": "FeatureCollection", "features": [{"type": "Feature",
+                    "properties": {"osm_id": 2315704, "name": "Boston"},
+                    "geometry": {"type": "Point", "coordinates": [-71.060511, 42.3554334]}}]}"#,
+            ),
+            _ => MockResponse::json(&format!("[{}]", BOSTON)),
+        })
+        .await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let g = Geocode::new("Boston".to_string());
+
+        match g.search_as(&c, NominatimFormat::Json).await.unwrap() {
+            GeocodeResults::Json(r) => assert_eq!(r[0].osm_id, 2315704),
+            r => panic!("unexpected {:?}", r),
+        }
+        match g.search_as(&c, NominatimFormat::GeoJson).await.unwrap() {
+            GeocodeResults::GeoJson(v) => {
+                assert_eq!(v["type"], "FeatureCollection");
+                assert_eq!(v["features"][0]["properties"]["name"], "Boston");
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let queries = vec![
+            Geocode::new("Boston".to_string()),
+            Geocode::builder()
+                .city("Springfield")
+                .feature_type(FeatureType::City)
+                .dedupe(false)
+                .build()
+                .unwrap(),
+        ];
+        let json = serde_json::to_string(&queries).unwrap();
+        let back: Vec<Geocode> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back[0].q.as_deref(), Some("Boston"));
+        assert_eq!(back[1].city.as_deref(), Some("Springfield"));
+        assert_eq!(back[1].feature_type, Some(FeatureType::City));
+        assert_eq!(back[1].dedupe, Some(false));
+
+        let r = ReverseGeocode {
+            zoom: Some(10),
+            ..ReverseGeocode::new(42.36, -71.06)
+        };
+        let back: ReverseGeocode =
+            serde_json::from_str(&serde_json::to_string(&r).unwrap()).unwrap();
+        assert_eq!((back.lat, back.lon, back.zoom), (42.36, -71.06, Some(10)));
+
+        let c = Config {
+            email: Some("me@example.com".to_string()),
+            rate_limit: Some(Duration::from_millis(1500)),
+            ..Default::default()
+        };
+        let back: Config = serde_json::from_str(&serde_json::to_string(&c).unwrap()).unwrap();
+        assert_eq!(back.url, c.url);
+        assert_eq!(back.email, c.email);
+        assert_eq!(back.rate_limit, c.rate_limit);
+        let partial: Config = serde_json::from_str(r#"{"timeout": 5}"#).unwrap();
+        assert_eq!(
+            (partial.timeout, partial.rate_limit),
+            (5, Some(Duration::from_secs(1)))
+        );
+    }
+
+    #[test]
+    fn test_with_param() {
+        let mut g = Geocode::new("Springfield".to_string());
+        g.with_param("countrycodes", "us")
+            .unwrap()
+            .with_param("limit", "3")
+            .unwrap();
+        let params = g.to_params();
+        assert_eq!(params.get("countrycodes").map(|v| v.as_str()), Some("us"));
+        assert_eq!(params.get("limit").map(|v| v.as_str()), Some("3"));
+
+        assert!(matches!(
+            g.with_param("format", "xml"),
+            Err(Error::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            g.with_param("limit", "3\r\nX-Evil: 1"),
+            Err(Error::InvalidQuery(_))
+        ));
+        assert!(!g.to_params().contains_key("format"));
+    }
+
+    #[tokio::test]
+    async fn test_exclude_place_ids() {
+        let (server, seen) = recording_server(format!("[{}]", BOSTON)).await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let mut g = Geocode::new("Springfield".to_string());
+        let first = g.search(&c).await.unwrap();
+
+        g.exclude_place_ids = Some(first.iter().map(|r| r.place_id).chain([7, 8]).collect());
+        g.search(&c).await.unwrap();
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].query("exclude_place_ids"), None);
+        assert_eq!(seen[1].query("exclude_place_ids"), Some("1%2C7%2C8"));
+
+        g.with_param("exclude_place_ids", "8, 9").unwrap();
+        assert_eq!(g.exclude_place_ids, Some(vec![1, 7, 8, 9]));
+        assert_eq!(g.to_params()["exclude_place_ids"], "1,7,8,9");
+        assert!(matches!(
+            g.with_param("exclude_place_ids", "9,x"),
+            Err(Error::InvalidQuery(_))
+        ));
+        assert_eq!(g.exclude_place_ids, Some(vec![1, 7, 8, 9]));
+    }
+
+    #[test]
+    fn test_count_by_country() {
+        let g = Geocode::builder()
+            .q("Springfield")
+            .addressdetails(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            g.to_params().get("addressdetails").map(|v| v.as_str()),
+            Some("1")
+        );
+
+        let in_country = |code: &str| {
+            Place::boston().address(&[("city", "Springfield"), ("country_code", code)])
+        };
+        let results: Vec<GeocodeResponse> = [
+            in_country("us"),
+            in_country("au"),
+            in_country("us"),
+            Place::boston(),
+        ]
+        .iter()
+        .map(Place::build)
+        .collect();
+        assert_eq!(results[0].country_code(), Some("us"));
+        assert_eq!(results[3].country_code(), None);
+        let counts = results.count_by_country();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["us"], 2);
+        assert_eq!(counts["au"], 1);
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let g = Geocode::new("Springfield".to_string());
+        assert!(!g.to_params().contains_key("dedupe"));
+
+        let g = Geocode::builder()
+            .q("Springfield")
+            .dedupe(false)
+            .build()
+            .unwrap();
+        assert_eq!(g.to_params().get("dedupe").map(|v| v.as_str()), Some("0"));
+    }
+
+    #[test]
+    fn test_feature_type() {
+        let g = Geocode::builder()
+            .q("Springfield")
+            .feature_type(FeatureType::City)
+            .build()
+            .unwrap();
+        assert_eq!(
+            g.to_params().get("featureType").map(|v| v.as_str()),
+            Some("city")
+        );
+
+        let g: Geocode = serde_json::from_str(r#"{"q": "Ohio", "feature_type": "state"}"#).unwrap();
+        assert_eq!(g.feature_type, Some(FeatureType::State));
+    }
+
+    #[test]
+    fn test_polygon_geojson() {
+        let g = Geocode::builder()
+            .q("Boston")
+            .polygon_geojson(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            g.to_params().get("polygon_geojson").map(|v| v.as_str()),
+            Some("1")
+        );
+
+        let plain: GeocodeResponse = serde_json::from_str(BOSTON).unwrap();
+        assert!(plain.geojson.is_none());
+
+        let r = Place::boston()
+            .geojson(serde_json::json!({
+                "type": "Polygon",
+                "coordinates": [[[-71.19, 42.22], [-70.80, 42.22], [-70.80, 42.40], [-71.19, 42.22]]]
+            }))
+            .build();
+        let geojson = r.geojson.unwrap();
+        assert_eq!(geojson["type"], "Polygon");
+        assert_eq!(geojson["coordinates"][0].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_polygon_threshold() {
+        let g = Geocode::builder()
+            .q("Boston")
+            .polygon_geojson(true)
+            .polygon_threshold(0.001)
+            .build()
+            .unwrap();
+        assert_eq!(
+            g.to_params().get("polygon_threshold").map(|v| v.as_str()),
+            Some("0.001")
+        );
+
+        let mut kml = Geocode::new("Boston".to_string());
+        kml.with_param("polygon_kml", "1").unwrap();
+        kml.polygon_threshold = Some(0.0);
+        assert!(kml.validate().is_ok());
+
+        let no_polygon = Geocode::builder().q("Boston").polygon_threshold(0.001);
+        assert!(matches!(no_polygon.build(), Err(Error::InvalidQuery(_))));
+        for t in [-0.1, f64::NAN] {
+            let negative = Geocode::builder()
+                .q("Boston")
+                .polygon_geojson(true)
+                .polygon_threshold(t);
+            assert!(matches!(negative.build(), Err(Error::InvalidQuery(_))));
+        }
+
+        let mut passed = Geocode::new("Boston".to_string());
+        passed.polygon_geojson = true;
+        passed.with_param("polygon_threshold", "0.001").unwrap();
+        assert_eq!(passed.polygon_threshold, Some(0.001));
+        assert!(passed.validate().is_ok());
+        assert!(matches!(
+            passed.with_param("polygon_threshold", "fine"),
+            Err(Error::InvalidQuery(_))
+        ));
+        passed.with_param("polygon_threshold", "-1").unwrap();
+        assert!(matches!(passed.validate(), Err(Error::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn test_filter_class() {
+        let with = |class: &str, place_type: &str| Place::boston().class(class, place_type).build();
+        let results = [
+            with("boundary", "administrative"),
+            with("place", "city"),
+            with("boundary", "postal_code"),
+            with("amenity", "cafe"),
+        ];
+        assert!(results[0].is_administrative());
+        assert!(!results[2].is_administrative());
+        assert!(results[2].is_class("boundary", None));
+
+        let kinds = |v: Vec<&GeocodeResponse>| -> Vec<String> {
+            v.iter().map(|r| r.place_type.clone()).collect()
+        };
+        assert_eq!(
+            kinds(results.filter_class("boundary", None)),
+            ["administrative", "postal_code"]
+        );
+        assert_eq!(kinds(results.filter_class("place", Some("city"))), ["city"]);
+        assert!(results.filter_class("place", Some("town")).is_empty());
+    }
+
+    #[test]
+    fn test_group_by_rank() {
+        let with = |name: &str, rank: u64| Place::boston().name(name).place_rank(rank).build();
+        let results = [
+            with("Boston", 16),
+            with("Boston Common", 30),
+            with("Lincolnshire", 12),
+            with("Boston", 16),
+            with("Massachusetts", 8),
+        ];
+
+        let groups = results.group_by_rank();
+        assert_eq!(
+            groups.keys().copied().collect::<Vec<_>>(),
+            vec![8, 12, 16, 30]
+        );
+        assert_eq!(groups[&16].len(), 2);
+        assert_eq!(groups[&30][0].name, "Boston Common");
+
+        let labels: Vec<&str> = groups.keys().map(|r| rank_label(*r)).collect();
+        assert_eq!(labels, vec!["state", "county", "city", "address"]);
+        assert_eq!(results[4].rank_label(), "state");
+    }
+
+    #[test]
+    fn test_is_broad_match() {
+        let city = Place::boston().build();
+        assert!(!city.is_broad_match());
+
+        let country = Place::boston().place_rank(4).addresstype("country").build();
+        assert!(country.is_broad_match());
+
+        let street = Geocode::builder()
+            .street("1 Nowhere Lane")
+            .country("United States")
+            .build()
+            .unwrap();
+        assert!(country.is_broad_match_for(&street));
+        assert!(city.is_broad_match_for(&street));
+
+        let by_city = Geocode::builder().city("Boston").build().unwrap();
+        assert!(!city.is_broad_match_for(&by_city));
+    }
+
+    #[test]
+    fn test_bbox_orientation() {
+        let r: GeocodeResponse = serde_json::from_str(BOSTON).unwrap();
+        let b = r.bbox();
+        assert_eq!(b.xmin, -71.1912442);
+        assert_eq!(b.ymin, 42.2279112);
+        assert_eq!(b.xmax, -70.8044881);
+        assert_eq!(b.ymax, 42.4008613);
+    }
+
+    #[test]
+    fn test_bbox_from_response() {
+        let r: GeocodeResponse = serde_json::from_str(BOSTON).unwrap();
+        let b = BoundingBox::from(&r);
+        assert_eq!((b.xmin, b.xmax), (-71.1912442, -70.8044881));
+        assert_eq!((b.ymin, b.ymax), (42.2279112, 42.4008613));
+
+        let b: BoundingBox = r.into();
+        assert!(b.validate().is_ok());
+        assert!(b.xmin < b.xmax && b.ymin < b.ymax);
+    }
+
+    #[tokio::test]
+    async fn test_overpass_config() {
+        let c: Config = Config {
+            url: "https://nominatim.openstreetmap.org/search".to_string(),
+            timeout: 25,
+            ..Default::default()
+        };
+
+        let g = Geocode::new("Cambridge, MA".to_string());
+        let resp = g.search(&c).await.unwrap();
+        let (oc, b) =
+            resp[0].overpass_config("amenity", "cafe", "https://overpass-api.de/api/interpreter");
+        assert_eq!(oc.key, "amenity");
+        let cafes = b.search(&oc).await.unwrap();
+        assert!(!cafes.elements.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_geocode_offline() {
+        let (server, seen) = recording_server(format!("[{}]", BOSTON)).await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let resp = Geocode::new("Boston".to_string()).search(&c).await.unwrap();
+        assert_eq!(resp[0].lat, 42.3554334);
+        assert_eq!(resp[0].lon, -71.060511);
+
+        let seen = seen.lock().unwrap();
+        assert!(seen[0].target.starts_with("/search?"));
+        assert_eq!(seen[0].query("format"), Some("json"));
+        assert_eq!(seen[0].query("q"), Some("Boston"));
+    }
+
+    #[tokio::test]
+    async fn test_reverse_geocode_offline() {
+        let (server, seen) = recording_server(BOSTON.to_string()).await;
+        let c = Config {
+            url: server.url("/reverse"),
+            ..Default::default()
+        };
+        let g = ReverseGeocode {
+            lat: 42.3554334,
+            lon: -71.060511,
+            zoom: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(g.search(&c).await.unwrap().osm_id, 2315704);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].query("lat"), Some("42.3554334"));
+        assert_eq!(seen[0].query("lon"), Some("-71.060511"));
+        assert_eq!(seen[0].query("zoom"), Some("10"));
+
+        let g = ReverseGeocode {
+            zoom: Some(MAX_ZOOM + 1),
+            ..g
+        };
+        assert!(matches!(g.to_params(), Err(Error::InvalidQuery(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reverse_details() {
+        let with_address = Place::boston()
+            .address(&[("road", "Tremont Street"), ("country_code", "us")])
+            .json();
+        let (server, seen) = recording_server(with_address).await;
+        let c = Config {
+            url: server.url("/reverse"),
+            ..Default::default()
+        };
+        let g = ReverseGeocode {
+            addressdetails: true,
+            namedetails: true,
+            layer: Some("address".to_string()),
+            ..ReverseGeocode::new(42.3554334, -71.060511)
+        };
+        let resp = g.search(&c).await.unwrap();
+        assert_eq!(resp.country_code(), Some("us"));
+        assert_eq!(
+            resp.address.unwrap().get("road").map(String::as_str),
+            Some("Tremont Street")
+        );
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].query("addressdetails"), Some("1"));
+        assert_eq!(seen[0].query("namedetails"), Some("1"));
+        assert_eq!(seen[0].query("extratags"), None);
+        assert_eq!(seen[0].query("layer"), Some("address"));
+    }
+
+    #[tokio::test]
+    async fn test_search_with_retry() {
+        let server = MockServer::start(vec![
+            MockResponse::new(429, "slow down").header("Retry-After", "0"),
+            MockResponse::new(503, "overloaded"),
+            MockResponse::json(&format!("[{}]", BOSTON)),
+        ])
+        .await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        let g = Geocode::new("Boston".to_string());
+        assert_eq!(
+            g.search_with_retry(&c, &policy).await.unwrap()[0].osm_id,
+            2315704
+        );
+
+        let server = MockServer::start(vec![
+            MockResponse::new(429, "slow down").header("Retry-After", "0")
+        ])
+        .await;
+        let c = Config {
+            url: server.url("/reverse"),
+            ..Default::default()
+        };
+        let r = ReverseGeocode::new(42.3554334, -71.060511);
+        match r.search_with_retry(&c, &policy).await {
+            Err(Error::Unavailable {
+                status: 429,
+                retry_after,
+            }) => assert_eq!(retry_after, Some(Duration::ZERO)),
+            other => panic!("expected 429, got {:?}", other.map(|r| r.osm_id)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reverse_not_found() {
+        let ocean = ReverseGeocode::new(0.0, -30.0);
+        for body in [
+            r#"{"error": "Unable to geocode"}"#,
+            r#"{"error": {"code": 404, "message": "Unable to geocode"}}"#,
+        ] {
+            let server = MockServer::start(vec![MockResponse::json(body)]).await;
+            let c = Config {
+                url: server.url("/reverse"),
+                ..Default::default()
+            };
+            match ocean.search(&c).await {
+                Err(Error::NotFound(m)) => assert_eq!(m, "Unable to geocode"),
+                other => panic!("expected NotFound, got {:?}", other.map(|r| r.osm_id)),
+            }
+            assert!(matches!(
+                ocean.wikidata_id(&c).await,
+                Err(Error::NotFound(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_autocomplete() {
+        let (server, seen) = recording_server(format!("[{}]", BOSTON)).await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let ac = Autocomplete::new(c, Duration::from_millis(50));
+        let typing = async {
+            let (a, b) = tokio::join!(ac.query("Bo"), async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ac.query("Bos").await
+            });
+            let c = ac.query("Boston").await;
+            (a, b, c)
+        };
+        let (a, b, c) = typing.await;
+        assert!(matches!(a, Err(Error::Cancelled)));
+        assert_eq!(b.unwrap()[0].name, "Boston");
+        assert_eq!(c.unwrap().len(), 1);
+
+        let seen = seen.lock().unwrap();
+        let queries: Vec<&str> = seen.iter().filter_map(|r| r.query("q")).collect();
+        assert_eq!(queries, ["Bos", "Boston"]);
+    }
+
+    #[tokio::test]
+    async fn test_wikidata_id() {
+        let with_tags = Place::boston()
+            .extratags(&[("wikidata", "Q100"), ("population", "675647")])
+            .json();
+        let (server, seen) = recording_server(with_tags).await;
+        let c = Config {
+            url: server.url("/reverse"),
+            ..Default::default()
+        };
+        let g = ReverseGeocode::new(42.3554334, -71.060511);
+        assert_eq!(g.wikidata_id(&c).await.unwrap().as_deref(), Some("Q100"));
+        assert_eq!(seen.lock().unwrap()[0].query("extratags"), Some("1"));
+
+        let (server, _) = recording_server(BOSTON.to_string()).await;
+        let c = Config {
+            url: server.url("/reverse"),
+            ..Default::default()
+        };
+        assert_eq!(g.wikidata_id(&c).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_best_guesses() {
+        let result = |place_id: u64, osm_id: u64, importance: f64, name: &str| {
+            Place::boston()
+                .place_id(place_id)
+                .osm_id(osm_id)
+                .importance(importance)
+                .display_name(name)
+        };
+        let results = places(&[
+            result(1, 10, 0.65, "Cambridge, Massachusetts"),
+            result(2, 20, 0.71, "Cambridge, England"),
+            result(3, 10, 0.6, "Cambridge, Middlesex County, Massachusetts"),
+            result(4, 30, 0.01, "Cambridge Street"),
+            result(5, 40, 0.3, "Cambridge, Ontario"),
+        ]);
+        let (server, seen) = recording_server(results).await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+
+        let g = Geocode::new("Cambridge".to_string());
+        let names = |rs: Vec<GeocodeResponse>| -> Vec<String> {
+            rs.into_iter().map(|r| r.display_name).collect()
+        };
+        assert_eq!(
+            names(g.best_guesses(&c, 2).await.unwrap()),
+            ["Cambridge, England", "Cambridge, Massachusetts"]
+        );
+        assert_eq!(
+            names(g.best_guesses(&c, 5).await.unwrap()),
+            [
+                "Cambridge, England",
+                "Cambridge, Massachusetts",
+                "Cambridge, Ontario"
+            ]
+        );
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].query("limit"), Some("4"));
+        assert_eq!(seen[0].query("addressdetails"), Some("1"));
+        assert_eq!(seen[1].query("limit"), Some("10"));
+    }
+
+    #[tokio::test]
+    async fn test_overpass_config_offline() {
+        let server = MockServer::with_handler(|req| {
+            if req.target.starts_with("/search") {
+                MockResponse::json(&format!("[{}]", BOSTON))
+            } else {
+                MockResponse::json(include_str!("../fixtures/overpass_london_cafes.json"))
+            }
+        })
+        .await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let resp = Geocode::new("Boston".to_string()).search(&c).await.unwrap();
+        let (oc, b) = resp[0].overpass_config("amenity", "cafe", &server.url("/api/interpreter"));
+        assert_eq!(oc.key, "amenity");
+        assert_eq!(b.ymin, 42.2279112);
+        assert_eq!(b.search(&oc).await.unwrap().elements.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_email_param() {
+        let (server, seen) = recording_server(format!("[{}]", BOSTON)).await;
+        let c = Config {
+            url: server.url("/search"),
+            email: Some("ops@example.com".to_string()),
+            ..Default::default()
+        };
+        Geocode::new("Boston".to_string()).search(&c).await.unwrap();
+
+        let (rserver, rseen) = recording_server(BOSTON.to_string()).await;
+        let rc = Config {
+            url: rserver.url("/reverse"),
+            ..c
+        };
+        ReverseGeocode {
+            lat: 42.3554334,
+            lon: -71.060511,
+            ..Default::default()
+        }
+        .search(&rc)
+        .await
+        .unwrap();
+
+        for req in [&seen.lock().unwrap()[0], &rseen.lock().unwrap()[0]] {
+            assert_eq!(req.query("email"), Some("ops%40example.com"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_agent() {
+        let (server, seen) = recording_server(format!("[{}]", BOSTON)).await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let g = Geocode::new("Boston".to_string());
+        g.search(&c).await.unwrap();
+
+        let c = Config {
+            user_agent: Some("my-app/1.0 (ops@example.com)".to_string()),
+            ..c
+        };
+        g.search(&c).await.unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].header("user-agent"), Some(http::APP_USER_AGENT));
+        assert_eq!(
+            seen[1].header("user-agent"),
+            Some("my-app/1.0 (ops@example.com)")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reverse_zoom() {
+        let (server, seen) = recording_server(BOSTON.to_string()).await;
+        let c = Config {
+            url: server.url("/reverse"),
+            ..Default::default()
+        };
+        let g = ReverseGeocode {
+            zoom: Some(10),
+            ..ReverseGeocode::new(42.3554334, -71.060511)
+        };
+        g.search(&c).await.unwrap();
+        assert_eq!(seen.lock().unwrap()[0].query("zoom"), Some("10"));
+
+        let g = ReverseGeocode {
+            zoom: Some(19),
+            ..g
+        };
+        assert!(matches!(g.search(&c).await, Err(Error::InvalidQuery(_))));
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    const BOSTON_DETAILS: &str = include_str!("../fixtures/nominatim_boston_details.json");
+
+    #[tokio::test]
+    async fn test_details() {
+        let (server, seen) = recording_server(BOSTON_DETAILS.to_string()).await;
+        let c = Config {
+            url: server.url("/details"),
+            ..Default::default()
+        };
+
+        let resp = Details::PlaceId(297593038).search(&c).await.unwrap();
+        assert_eq!(resp.osm_id, 2315704);
+        assert_eq!(resp.names["name:ja"], "ボストン");
+        assert_eq!(
+            resp.breadcrumbs(),
+            vec!["United States", "Massachusetts", "Suffolk County", "Boston"]
+        );
+
+        Details::Osm(overpass::OsmType::Relation, 2315704)
+            .search(&c)
+            .await
+            .unwrap();
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].query("place_id"), Some("297593038"));
+        assert_eq!(seen[0].query("addressdetails"), Some("1"));
+        assert_eq!(seen[1].query("osmtype"), Some("R"));
+        assert_eq!(seen[1].query("osmid"), Some("2315704"));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        // Accept connections but never answer
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+        let c = Config {
+            url: format!("http://{}/search", addr),
+            timeout: 1,
+            ..Default::default()
+        };
+
+        let start = std::time::Instant::now();
+        match Geocode::new("Boston".to_string()).search(&c).await {
+            Err(Error::Http(e)) => assert!(e.is_timeout()),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_search_audited() {
+        let server = MockServer::start(vec![MockResponse::json(&format!("[{}]", BOSTON))]).await;
+        let c = Config {
+            url: server.url("/search"),
+            email: Some("me@example.com".to_string()),
+            ..Default::default()
+        };
+
+        let (resp, url) = Geocode::new("Boston".to_string())
+            .search_audited(&c)
+            .await
+            .unwrap();
+        assert!(!resp.is_empty());
+        assert_eq!(url.path(), "/search");
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["format"], "json");
+        assert_eq!(params["q"], "Boston");
+        assert_eq!(params["email"], "me@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_search_one() {
+        let server = MockServer::start(vec![
+            MockResponse::json(&format!("[{}]", BOSTON)),
+            MockResponse::json("[]"),
+        ])
+        .await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+        let g = Geocode::new("Boston".to_string());
+
+        assert_eq!(g.search_one(&c).await.unwrap().osm_id, 2315704);
+        assert!(matches!(g.search_one(&c).await, Err(Error::Empty)));
+    }
+
+    #[tokio::test]
+    async fn test_latin1_body() {
+        let json = places(&[Place::boston().name("São Paulo")]);
+        let latin1: Vec<u8> = json.chars().map(|c| c as u8).collect();
+        let server = MockServer::start(vec![MockResponse::new(200, latin1)
+            .header("Content-Type", "application/json; charset=ISO-8859-1")])
+        .await;
+        let c = Config {
+            url: server.url("/search"),
+            ..Default::default()
+        };
+
+        let resp = Geocode::new("São Paulo".to_string())
+            .search(&c)
+            .await
+            .unwrap();
+        assert_eq!(resp[0].name, "São Paulo");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_endpoint() {
+        let c = Config {
+            url: "https://overpass-api.de/api/interpreter".to_string(),
+            ..Default::default()
+        };
+        let res = Geocode::new("Boston".to_string()).search(&c).await;
+        assert!(matches!(res, Err(Error::LikelyWrongEndpoint(_))));
+        let res = ReverseGeocode::new(42.36, -71.06).search(&c).await;
+        assert!(matches!(res, Err(Error::LikelyWrongEndpoint(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reverse_stream() {
+        // Echo the latitude back as the osm_id so the order can be checked
+        let server = MockServer::with_handler(|req| {
+            let lat = req.query("lat").unwrap();
+            MockResponse::json(&Place::boston().osm_id(lat.parse().unwrap()).json())
+        })
+        .await;
+        let c = Config {
+            url: server.url("/reverse"),
+            rate_limit: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let points = stream::iter([3.0, 1.0, 2.0].map(|lat| Coordinate { lat, lon: 0.0 }));
+
+        let ids: Vec<u64> = ReverseGeocode::stream(points, &c, 2)
+            .map(|r| r.unwrap().osm_id)
+            .collect()
+            .await;
+        assert_eq!(ids, vec![3, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_shared_limiter() {
+        let (server, seen) = recording_server(BOSTON.to_string()).await;
+        let limiter = Arc::new(RateLimiter::new(Duration::from_millis(50)));
+        let c = Config {
+            url: server.url("/reverse"),
+            rate_limit: None,
+            ..Default::default()
+        }
+        .with_shared_limiter(limiter);
+
+        // Each task gets its own clone, all sharing the limiter
+        let start = tokio::time::Instant::now();
+        let tasks: Vec<_> = (0..3)
+            .map(|_| {
+                let c = c.clone();
+                tokio::spawn(async move { ReverseGeocode::new(42.36, -71.06).search(&c).await })
+            })
+            .collect();
+        for t in tasks {
+            t.await.unwrap().unwrap();
+        }
+        assert_eq!(seen.lock().unwrap().len(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let json = serde_json::to_string(&c).unwrap();
+        let back: Config = serde_json::from_str(&json).unwrap();
+        assert!(back.limiter.is_none());
     }
 
     #[tokio::test]
@@ -240,11 +2374,13 @@ mod tests {
         let c: Config = Config {
             url: "https://nominatim.openstreetmap.org/reverse".to_string(),
             timeout: 25,
+            ..Default::default()
         };
 
         let g = ReverseGeocode {
             lat: 42.3554334,
             lon: -71.060511,
+            ..Default::default()
         };
 
         let resp = g.search(&c).await.unwrap();
@@ -6,7 +6,7 @@
 //!
 //! # Library Example
 //! ```rust
-//! use osm_rs::overpass::{BoundingBox, Config};
+//! use osm_rs::overpass::{BoundingBox, Config, Search};
 //!
 //! #[tokio::main]
 //! async fn main() {
@@ -15,22 +15,38 @@
 //!       timeout: 25,
 //!       key: "amenity".to_string(),
 //!       val: "cafe".to_string(),
+//!       ..Default::default()
 //!   };
 //!
 //!   let b: BoundingBox = BoundingBox {
-//!       xmin: 51.305219521963295,
-//!       ymin: -0.7690429687500001,
-//!       xmax: 51.82219818336938,
-//!       ymax: 0.5273437500000064,
+//!       xmin: -0.7690429687500001,
+//!       ymin: 51.305219521963295,
+//!       xmax: 0.5273437500000064,
+//!       ymax: 51.82219818336938,
 //!   };
 //!
 //!   let resp = b.search(&c).await.expect("failed query");
 //! }
 //! ```
-use reqwest::{Client, Error};
+use crate::http::{self, read_body};
+use crate::ratelimit::RateLimiter;
+use crate::retry::{self, RetryPolicy};
+use crate::Error;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream, StreamExt};
+use reqwest::header::{HeaderName, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use reqwest::{StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::deserialize_number_from_string;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
 /// Major semiaxis of WGS-84 geoidal reference
 const WGS84A: f64 = 6378137.0;
@@ -38,16 +54,656 @@ const WGS84A: f64 = 6378137.0;
 /// Minor semiaxis of WGS-84 geoidal reference
 const WGS84B: f64 = 6356752.3;
 
-/// Query configuration
-#[derive(Debug)]
+/// Size in pixels of a slippy map tile
+const TILE_SIZE: f64 = 256.0;
+
+/// Lowest `[timeout:N]`, in seconds, sent when scaling the timeout down for
+/// a small area, see [`Config::auto_timeout`]
+pub const MIN_TIMEOUT: u8 = 10;
+
+/// Area in km² a scaled timeout allows per additional second
+const KM2_PER_TIMEOUT_SECOND: f64 = 100.0;
+
+/// Keys that define what a feature is, in priority order
+const PRIMARY_KEYS: &[&str] = &[
+    "amenity",
+    "shop",
+    "tourism",
+    "leisure",
+    "office",
+    "craft",
+    "healthcare",
+    "historic",
+    "public_transport",
+    "railway",
+    "highway",
+    "man_made",
+    "natural",
+    "landuse",
+    "building",
+];
+
+/// Deprecated tags, as `key=value` or a bare `key` when the whole key is
+/// deprecated. See <https://wiki.openstreetmap.org/wiki/Deprecated_features>.
+const DEPRECATED_TAGS: &[&str] = &[
+    "amenity=shop",
+    "amenity=ev_charging",
+    "amenity=real_estate",
+    "amenity=register_office",
+    "highway=ford",
+    "shop=fish",
+    "shop=organic",
+    "created_by",
+    "is_in",
+];
+
+/// Query configuration. The cache, limiter and client are not
+/// serialized and are left unset when deserializing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub url: String,
     pub timeout: u8,
     pub key: String,
     pub val: String,
+    /// Return only the changes between two dates, e.g.
+    /// `("2023-01-01T00:00:00Z", "2023-06-01T00:00:00Z")`, see
+    /// [`Config::validate`]
+    pub diff_dates: Option<(String, String)>,
+    /// Send `diff_dates` as an augmented diff (`[adiff:...]`), in which
+    /// Overpass also reports elements that left the selection, e.g. by
+    /// losing the tag, rather than only those created or deleted.
+    /// [`Search::search_adiff`] sets it and parses the response.
+    pub augmented_diff: bool,
+    /// Response format, see [`OutputFormat`]
+    pub format: OutputFormat,
+    /// Element types to select, nodes by default
+    pub element_type: ElementType,
+    /// Detail of the `out` statement, see [`OutputDetail`]
+    pub output: OutputDetail,
+    /// Sort the output by quadtile (`out ... qt`), which is faster than the
+    /// default sort by id
+    pub quadtile: bool,
+    /// Abort with [`Error::ResponseTooLarge`] once the body exceeds this many bytes
+    pub max_response_bytes: Option<usize>,
+    /// Reuse responses to identical queries, see [`Cache`]
+    #[serde(skip)]
+    pub cache: Option<Arc<Cache>>,
+    /// User agent identifying the application, defaults to `osm-rs/<version>`
+    pub user_agent: Option<String>,
+    /// Fixed pause after each request issued by batch helpers such as
+    /// [`search_all`]
+    pub delay_between: Option<Duration>,
+    /// Further selectors every element must match besides `key=val`
+    pub filters: Vec<Filter>,
+    /// Memory limit in bytes the server may use for the query, sent as
+    /// `[maxsize:...]`. Overpass applies 512 MiB when unset.
+    pub maxsize: Option<u64>,
+    /// Redirects to follow before giving up, `Some(0)` to follow none
+    pub max_redirects: Option<usize>,
+    /// Ask for gzip or deflate compressed responses, on by default. Only
+    /// takes effect when built with the `gzip` or `deflate` feature, which
+    /// enable the matching reqwest features; responses are requested
+    /// uncompressed otherwise.
+    pub compression: bool,
+    /// Scale the `[timeout:N]` of box and radius searches with their area,
+    /// from [`MIN_TIMEOUT`] for small areas up to `timeout`, see
+    /// [`scaled_timeout`]. On by default; when off `timeout` is sent as is.
+    pub auto_timeout: bool,
+    /// Throttle shared by every clone of this config and awaited before each
+    /// request, see [`Config::with_shared_limiter`]
+    #[serde(skip)]
+    pub limiter: Option<Arc<RateLimiter>>,
+    /// Return at most this many elements, e.g. `out center 500;`. The server
+    /// keeps whichever come first in its output order, which is arbitrary
+    /// for the caller unless sorted, e.g. with `quadtile`.
+    pub limit: Option<u32>,
+    /// Client to send requests with instead of building one per request,
+    /// see [`Config::with_client`]
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// How queries are sent, POST by default, see [`HttpMethod`]
+    pub method: HttpMethod,
+    /// Retry requests that were throttled, hit an overloaded server or
+    /// could not connect, see [`RetryPolicy`]. Each request is sent once
+    /// when unset.
+    pub retry: Option<RetryPolicy>,
+}
+
+impl Config {
+    /// Start building a config from the defaults
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Check that `diff_dates` are UTC timestamps such as
+    /// `2023-01-01T00:00:00Z`, the form Overpass expects. Checked by
+    /// [`ConfigBuilder::build`] and before every request.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some((from, to)) = &self.diff_dates {
+            for date in [from, to] {
+                if !is_timestamp(date) {
+                    return Err(Error::InvalidConfig(format!(
+                        "diff date `{}` is not of the form 2023-01-01T00:00:00Z",
+                        date
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy of this config using `url`
+    pub fn with_url(&self, url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Copy of this config sending every request, including those of its
+    /// clones, through `limiter`
+    pub fn with_shared_limiter(&self, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            limiter: Some(limiter),
+            ..self.clone()
+        }
+    }
+
+    /// Copy of this config sending requests with `client`, reusing its
+    /// connections. The client's own user agent, redirect policy and
+    /// compression settings apply in place of those of the config.
+    pub fn with_client(&self, client: reqwest::Client) -> Self {
+        Self {
+            client: Some(client),
+            ..self.clone()
+        }
+    }
+
+    /// Copy of this config using `timeout`
+    pub fn with_timeout(&self, timeout: u8) -> Self {
+        Self {
+            timeout,
+            ..self.clone()
+        }
+    }
+
+    /// Copy of this config filtering on `key`
+    pub fn with_key(&self, key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Copy of this config filtering on `val`
+    pub fn with_val(&self, val: impl Into<String>) -> Self {
+        Self {
+            val: val.into(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Builds a [`Config`], defaulting to the public Overpass instance with a 25s timeout
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::Config;
+///
+/// let c = Config::builder()
+///     .url("https://overpass.kumi.systems/api/interpreter")
+///     .timeout(60)
+///     .tag("amenity", "cafe")
+///     .build()
+///     .unwrap();
+/// assert_eq!(c.val, "cafe");
+/// ```
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    inner: Config,
+}
+
+impl ConfigBuilder {
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.inner.url = url.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: u8) -> Self {
+        self.inner.timeout = timeout;
+        self
+    }
+
+    /// Filter nodes by `key=val`
+    pub fn tag(mut self, key: impl Into<String>, val: impl Into<String>) -> Self {
+        self.inner.key = key.into();
+        self.inner.val = val.into();
+        self
+    }
+
+    pub fn diff_dates(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.inner.diff_dates = Some((from.into(), to.into()));
+        self
+    }
+
+    pub fn augmented_diff(mut self, augmented: bool) -> Self {
+        self.inner.augmented_diff = augmented;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.inner.format = format;
+        self
+    }
+
+    pub fn element_type(mut self, element_type: ElementType) -> Self {
+        self.inner.element_type = element_type;
+        self
+    }
+
+    pub fn output(mut self, output: OutputDetail) -> Self {
+        self.inner.output = output;
+        self
+    }
+
+    pub fn quadtile(mut self, quadtile: bool) -> Self {
+        self.inner.quadtile = quadtile;
+        self
+    }
+
+    pub fn max_response_bytes(mut self, max: usize) -> Self {
+        self.inner.max_response_bytes = Some(max);
+        self
+    }
+
+    pub fn cache(mut self, cache: Arc<Cache>) -> Self {
+        self.inner.cache = Some(cache);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.inner.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn delay_between(mut self, delay: Duration) -> Self {
+        self.inner.delay_between = Some(delay);
+        self
+    }
+
+    pub fn maxsize(mut self, bytes: u64) -> Self {
+        self.inner.maxsize = Some(bytes);
+        self
+    }
+
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.inner.max_redirects = Some(max);
+        self
+    }
+
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.inner.compression = compression;
+        self
+    }
+
+    pub fn auto_timeout(mut self, auto_timeout: bool) -> Self {
+        self.inner.auto_timeout = auto_timeout;
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.inner.limit = Some(limit);
+        self
+    }
+
+    pub fn method(mut self, method: HttpMethod) -> Self {
+        self.inner.method = method;
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.inner.retry = Some(policy);
+        self
+    }
+
+    /// Add a selector on top of the one set with [`ConfigBuilder::tag`]
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.inner.filters.push(filter);
+        self
+    }
+
+    /// Build the config, checking the URL is http(s) and a tag was given
+    pub fn build(self) -> Result<Config, Error> {
+        let url = Url::parse(&self.inner.url)
+            .map_err(|e| Error::InvalidConfig(format!("bad url `{}`: {}", self.inner.url, e)))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(Error::InvalidConfig(format!(
+                "unsupported url scheme `{}`",
+                url.scheme()
+            )));
+        }
+        if self.inner.key.is_empty() && self.inner.filters.is_empty() {
+            return Err(Error::InvalidConfig("missing tag".to_string()));
+        }
+        self.inner.validate()?;
+        Ok(self.inner)
+    }
+}
+
+/// Tag selector added to a query through [`Config::filters`]
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{BoundingBox, Config, Filter, Search};
+///
+/// let c = Config::builder()
+///     .filter(Filter::any_of("amenity", vec!["cafe".into(), "restaurant".into()]))
+///     .filter(Filter::Eq("wheelchair".into(), "yes".into()))
+///     .build()
+///     .unwrap();
+/// let b = BoundingBox { xmin: -0.2, ymin: 51.4, xmax: 0.1, ymax: 51.6 };
+/// assert_eq!(
+///     b.build_query(&c),
+///     "[out:json][timeout:15];node[\"amenity\"~\"^(cafe|restaurant)$\"][\"wheelchair\"=\"yes\"](51.4,-0.2,51.6,0.1);out center;"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// `["key"="value"]`
+    Eq(String, String),
+    /// `["key"~"^(a|b)$"]`, matching any of the values exactly
+    AnyOf(String, Vec<String>),
+}
+
+impl Filter {
+    /// Match `key` equal to any of `values`
+    pub fn any_of(key: impl Into<String>, values: Vec<String>) -> Self {
+        Filter::AnyOf(key.into(), values)
+    }
+
+    /// Overpass QL selector
+    pub fn to_ql(&self) -> String {
+        match self {
+            Filter::Eq(k, v) => format!("[\"{}\"=\"{}\"]", ql_escape(k), ql_escape(v)),
+            Filter::AnyOf(k, vs) => {
+                let alts: Vec<String> = vs.iter().map(|v| regex_escape(v)).collect();
+                format!(
+                    "[\"{}\"~\"^({})$\"]",
+                    ql_escape(k),
+                    ql_escape(&alts.join("|"))
+                )
+            }
+        }
+    }
+}
+
+/// Escape `s` for use inside a double quoted QL string
+fn ql_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape regex metacharacters so `s` matches literally
+fn regex_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Selectors for `key=val` followed by [`Config::filters`]
+fn tag_selectors(config: &Config) -> String {
+    let mut ql = String::new();
+    if !config.key.is_empty() {
+        ql.push_str(&format!(
+            "[\"{}\"=\"{}\"]",
+            ql_escape(&config.key),
+            ql_escape(&config.val)
+        ));
+    }
+    for f in &config.filters {
+        ql.push_str(&f.to_ql());
+    }
+    ql
+}
+
+/// In-memory cache of responses keyed on the endpoint URL, HTTP method and
+/// generated query, see [`Cache::key`], shared between configs through an
+/// `Arc`
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{Cache, Config};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let cache = Arc::new(Cache::new(Duration::from_secs(600)));
+/// let c = Config::builder()
+///     .tag("amenity", "cafe")
+///     .cache(cache.clone())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Cache {
+    ttl: Duration,
+    max_age: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+/// Entries a [`Cache`] holds by default before evicting the least recently
+/// used one
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// How long a [`Cache`] keeps an expired entry with an ETag for
+/// revalidation by default
+pub const DEFAULT_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug)]
+struct CacheEntry {
+    /// Stored or last revalidated
+    at: Instant,
+    /// Last returned or stored, for least recently used eviction
+    used: Instant,
+    etag: Option<String>,
+    resp: OverpassResponse,
+}
+
+impl CacheEntry {
+    /// Whether the entry is neither fresh nor kept for revalidation
+    fn is_dead(&self, ttl: Duration, max_age: Duration) -> bool {
+        let age = self.at.elapsed();
+        age >= ttl && (self.etag.is_none() || age >= max_age)
+    }
+}
+
+impl Cache {
+    /// Create an empty cache whose entries expire after `ttl`, holding at
+    /// most [`DEFAULT_CACHE_CAPACITY`] entries and keeping expired ones with
+    /// an ETag for up to [`DEFAULT_CACHE_MAX_AGE`]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_age: DEFAULT_CACHE_MAX_AGE,
+            capacity: DEFAULT_CACHE_CAPACITY,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Hold at most `capacity` entries, evicting the least recently used
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Drop expired entries with an ETag once `max_age` has passed since
+    /// they were stored or last revalidated
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Number of entries held, including expired ones not dropped yet
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Key of `query` sent with `config`, so that the same query sent to
+    /// another mirror or with another method is cached apart
+    pub fn key(config: &Config, query: &str) -> String {
+        let method = match config.method {
+            HttpMethod::Post => "POST",
+            HttpMethod::Get => "GET",
+        };
+        format!("{} {}\n{}", method, config.url, query)
+    }
+
+    /// Cached response for `key`, if still fresh. Expired entries with an
+    /// ETag are kept so they can be revalidated, up to the max age.
+    pub fn get(&self, key: &str) -> Option<OverpassResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let e = entries.get_mut(key)?;
+        if e.at.elapsed() < self.ttl {
+            e.used = Instant::now();
+            return Some(e.resp.clone());
+        }
+        if e.is_dead(self.ttl, self.max_age) {
+            entries.remove(key);
+        }
+        None
+    }
+
+    /// ETag the server sent with the cached response for `key`, unless the
+    /// entry is past its max age
+    pub fn etag(&self, key: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|e| !e.is_dead(self.ttl, self.max_age))
+            .and_then(|e| e.etag.clone())
+    }
+
+    pub fn insert(&self, key: String, resp: OverpassResponse) {
+        self.insert_with_etag(key, resp, None);
+    }
+
+    /// Store `resp` for `key`, first dropping dead entries and, when the
+    /// cache is full, the least recently used one
+    pub fn insert_with_etag(&self, key: String, resp: OverpassResponse, etag: Option<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| !e.is_dead(self.ttl, self.max_age));
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let lru = entries
+                .iter()
+                .min_by_key(|(_, e)| e.used)
+                .map(|(k, _)| k.clone());
+            if let Some(lru) = lru {
+                entries.remove(&lru);
+            }
+        }
+        let now = Instant::now();
+        let entry = CacheEntry {
+            at: now,
+            used: now,
+            etag,
+            resp,
+        };
+        entries.insert(key, entry);
+    }
+
+    /// Mark the entry for `key` fresh again, e.g. after a `304 Not Modified`
+    pub fn refresh(&self, key: &str) -> Option<OverpassResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.get_mut(key).map(|e| {
+            e.at = Instant::now();
+            e.used = e.at;
+            e.resp.clone()
+        })
+    }
+
+    /// Drop all entries
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Overpass output format
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// JSON, parsed into an [`OverpassResponse`]
+    #[default]
+    Json,
+    /// CSV with the given columns, e.g. `::id`, `amenity`, `name`.
+    /// Use [`Search::search_csv`] to get the raw text body.
+    Csv(Vec<String>),
+    /// XML with `out meta`. With the `xml` feature, `search_xml` parses
+    /// it into an `OsmXml`.
+    Xml,
+}
+
+/// HTTP method used to send queries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpMethod {
+    /// Query in the request body, suitable for queries of any length
+    #[default]
+    Post,
+    /// Query in the `data` URL parameter, which caches in front of a
+    /// mirror can store. Long queries may exceed URL length limits.
+    Get,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url: "https://overpass-api.de/api/interpreter".to_string(),
+            timeout: 25,
+            key: String::new(),
+            val: String::new(),
+            diff_dates: None,
+            augmented_diff: false,
+            format: OutputFormat::Json,
+            element_type: ElementType::Node,
+            max_response_bytes: None,
+            cache: None,
+            user_agent: None,
+            delay_between: None,
+            filters: vec![],
+            output: OutputDetail::default(),
+            quadtile: false,
+            maxsize: None,
+            max_redirects: None,
+            compression: true,
+            auto_timeout: true,
+            limiter: None,
+            limit: None,
+            client: None,
+            method: HttpMethod::Post,
+            retry: None,
+        }
+    }
 }
 
-/// Defines a bounding box by its coordinate boundaries (in radians)
+/// Defines a bounding box by its coordinate boundaries (in degrees)
+///
+/// `x` is the longitude and `y` the latitude.
 #[derive(Debug, Clone, Deserialize)]
 pub struct BoundingBox {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -60,122 +716,4729 @@ pub struct BoundingBox {
     pub ymax: f64,
 }
 
+/// Reasons a [`BoundingBox`] is rejected by [`BoundingBox::validate`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum BBoxError {
+    /// A coordinate is NaN or infinite
+    NotFinite,
+    /// `xmin >= xmax` or `ymin >= ymax`
+    Inverted,
+    /// A latitude lies outside [-90, 90]
+    LatitudeOutOfRange(f64),
+    /// A longitude lies outside [-180, 180]
+    LongitudeOutOfRange(f64),
+}
+
+impl std::fmt::Display for BBoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BBoxError::NotFinite => write!(f, "coordinate is not finite"),
+            BBoxError::Inverted => write!(f, "min coordinate is not below max"),
+            BBoxError::LatitudeOutOfRange(v) => write!(f, "latitude {} outside [-90, 90]", v),
+            BBoxError::LongitudeOutOfRange(v) => {
+                write!(f, "longitude {} outside [-180, 180]", v)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BBoxError {}
+
+/// A point in degrees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinate {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Defines a circular search area of `radius_m` meters around a point
+#[derive(Debug, Clone, Deserialize)]
+pub struct Around {
+    pub lat: f64,
+    pub lon: f64,
+    pub radius_m: f64,
+}
+
+/// Defines a search inside a polygon of `(lat, lon)` vertices in degrees,
+/// for regions a bounding box would over-select. The polygon is closed
+/// implicitly, so the first point need not be repeated.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Polygon {
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Defines a search for elements within `radius_m` meters of any element
+/// of a base set, e.g. benches near footpaths. The base set holds the
+/// `base_type` elements matching `base` inside `bbox`; the elements searched
+/// for are those matching the config, as in other searches.
+#[derive(Debug, Clone)]
+pub struct AroundSet {
+    pub base_type: ElementType,
+    pub base: Vec<Filter>,
+    pub bbox: BoundingBox,
+    pub radius_m: f64,
+}
+
+/// Defines a lookup of the areas, such as the city, state and country,
+/// containing the point at `lat`, `lon` in degrees
+#[derive(Debug, Clone, Deserialize)]
+pub struct IsIn {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Added by Overpass to a way id to form the id of its area
+const AREA_WAY_OFFSET: u64 = 2_400_000_000;
+/// Added by Overpass to a relation id to form the id of its area
+const AREA_RELATION_OFFSET: u64 = 3_600_000_000;
+
+/// Areas returned by [`IsIn::search`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AreasResponse {
+    pub version: f64,
+    pub generator: String,
+    pub osm3s: OSMMetaData,
+    pub elements: Vec<AreaElement>,
+    /// Note from the server, see [`OverpassResponse::remark`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+}
+
+/// Area of an [`AreasResponse`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AreaElement {
+    pub id: u64,
+    #[serde(default)]
+    pub tags: Tags,
+}
+
+/// Defines a search within a named area such as a city, optionally
+/// restricted to an OSM `admin_level` to tell e.g. the city from the county
+#[derive(Debug, Clone, Deserialize)]
+pub struct Area {
+    pub name: String,
+    pub admin_level: Option<u8>,
+}
+
 /// Metadata returned by the Overpass API
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct OSMMetaData {
     pub timestamp_osm_base: String,
     pub copyright: String,
 }
 
+/// Tags of an element, in the order the server sent them
+pub type Tags = indexmap::IndexMap<String, String>;
+
 /// Node data returned by the Overpass API
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct OverpassResponse {
     pub version: f64,
     pub generator: String,
     pub osm3s: OSMMetaData,
     pub elements: Vec<Node>,
+    /// Note from the server, e.g. `runtime error: Query timed out ...` when
+    /// the elements are incomplete, see [`OverpassResponse::is_truncated`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
 }
 
-/// Defines an OSM node
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Node {
-    pub id: u64,
-    pub lat: f64,
-    pub lon: f64,
-    pub tags: HashMap<String, String>,
+/// Amount of data printed for each element by the `out` statement
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputDetail {
+    /// Everything plus the center of ways and relations (`out center`)
+    #[default]
+    Center,
+    /// Tags and geometry of nodes, member lists of ways and relations
+    /// (`out body`). Ways and relations then have no location, so use
+    /// `search_tags` unless only nodes are selected.
+    Body,
+    /// Like `Body` without tags (`out skel`)
+    Skel,
+    /// Tags only, without any geometry (`out tags`). Use `search_tags`.
+    Tags,
+    /// Like `Body` plus the coordinates of every node of a way
+    /// (`out geom`), see [`Element::Way`]. Use `search_elements`.
+    Geometry,
 }
 
-/// Earth radius at a given latitude according to the WGS-84 ellipsoid
-fn wgs84_earth_radius(lat: f64) -> f64 {
-    let an = WGS84A * WGS84A * lat.cos();
-    let bn = WGS84B * WGS84B * lat.sin();
-    let ad = WGS84A * lat.cos();
-    let bd = WGS84B * lat.sin();
-    ((an * an + bn * bn) / (ad * ad + bd * bd)).sqrt()
+impl OutputDetail {
+    fn keyword(&self) -> &'static str {
+        match self {
+            OutputDetail::Center => "center",
+            OutputDetail::Body => "body",
+            OutputDetail::Skel => "skel",
+            OutputDetail::Tags => "tags",
+            OutputDetail::Geometry => "geom",
+        }
+    }
 }
 
-impl<'a> BoundingBox {
-    /// Construct a bounding box dist dkm away from point
-    pub fn from_point(lat: f64, lon: f64, dkm: f64) -> Self {
-        let dm = dkm * 1000.0;
-        let erad = wgs84_earth_radius(lat);
-        let prad = erad * lat.cos();
-        let dx = dm / prad;
-        let dy = dm / erad;
+/// Data returned by the `search_tags` methods, which parse elements without
+/// requiring a location
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagsResponse {
+    pub version: f64,
+    pub generator: String,
+    pub osm3s: OSMMetaData,
+    pub elements: Vec<TaggedElement>,
+    /// Note from the server, see [`OverpassResponse::remark`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+}
 
-        Self {
-            xmin: lon - dx,
-            ymin: lat - dy,
-            xmax: lon + dx,
-            ymax: lat + dy,
+/// Data returned by the `search_elements` methods, keeping nodes, ways and
+/// relations apart, e.g. for `nwr` queries with [`ElementType::All`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ElementsResponse {
+    pub version: f64,
+    pub generator: String,
+    pub osm3s: OSMMetaData,
+    pub elements: Vec<Element>,
+    /// Note from the server, see [`OverpassResponse::remark`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+}
+
+/// Element of an [`ElementsResponse`], told apart by its `type`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Element {
+    Node {
+        id: u64,
+        lat: f64,
+        lon: f64,
+        #[serde(default)]
+        tags: Tags,
+    },
+    Way {
+        id: u64,
+        /// Set by `out center`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        center: Option<Coordinate>,
+        /// Ids of the member nodes in order, set by `out body`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        nodes: Vec<u64>,
+        /// Coordinates of the member nodes in order, set by `out geom`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        geometry: Vec<Coordinate>,
+        #[serde(default)]
+        tags: Tags,
+    },
+    Relation {
+        id: u64,
+        /// Set by `out center`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        center: Option<Coordinate>,
+        /// Set by `out body`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        members: Vec<Member>,
+        #[serde(default)]
+        tags: Tags,
+    },
+}
+
+/// Member of a relation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Member {
+    #[serde(rename = "type")]
+    pub osm_type: OsmType,
+    #[serde(rename = "ref")]
+    pub id: u64,
+    #[serde(default)]
+    pub role: String,
+}
+
+impl Element {
+    pub fn id(&self) -> u64 {
+        match self {
+            Element::Node { id, .. } | Element::Way { id, .. } | Element::Relation { id, .. } => {
+                *id
+            }
         }
     }
 
-    /// Asynchronously search for nodes within the bounding box by tag
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use osm_rs::overpass::{BoundingBox, Config};
-    /// #[tokio::main]
-    /// async fn main() {
-    ///   let c: Config = Config {
-    ///       url: "https://overpass-api.de/api/interpreter".to_string(),
-    ///       timeout: 25,
-    ///       key: "amenity".to_string(),
-    ///       val: "cafe".to_string(),
-    ///   };
-    ///
-    ///   let b: BoundingBox = BoundingBox {
-    ///       xmin: 51.305219521963295,
-    ///       ymin: -0.7690429687500001,
-    ///       xmax: 51.82219818336938,
-    ///       ymax: 0.5273437500000064,
-    ///   };
-    ///
-    ///   let resp = b.search(&c).await.expect("failed query");
-    /// }
-    /// ```
-    pub async fn search(&self, config: &Config) -> Result<OverpassResponse, Error> {
-        let query = format!(
-            "[out:json];node[\"{}\"=\"{}\"]({},{},{},{});out center;",
-            config.key, config.val, self.xmin, self.ymin, self.xmax, self.ymax
-        );
+    pub fn osm_type(&self) -> OsmType {
+        match self {
+            Element::Node { .. } => OsmType::Node,
+            Element::Way { .. } => OsmType::Way,
+            Element::Relation { .. } => OsmType::Relation,
+        }
+    }
 
-        let client = Client::new();
-        let resp: OverpassResponse = client
-            .post(&config.url)
-            .body(query)
-            .send()
-            .await?
-            .json::<OverpassResponse>()
-            .await?;
+    pub fn tags(&self) -> &Tags {
+        match self {
+            Element::Node { tags, .. }
+            | Element::Way { tags, .. }
+            | Element::Relation { tags, .. } => tags,
+        }
+    }
 
-        Ok(resp)
+    /// Location of a node, or the center of a way or relation when the
+    /// query asked for it
+    pub fn location(&self) -> Option<Coordinate> {
+        match self {
+            Element::Node { lat, lon, .. } => Some(Coordinate {
+                lat: *lat,
+                lon: *lon,
+            }),
+            Element::Way { center, .. } | Element::Relation { center, .. } => *center,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tokio;
+/// Element of a [`TagsResponse`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaggedElement {
+    #[serde(rename = "type", default)]
+    pub osm_type: OsmType,
+    pub id: u64,
+    #[serde(default)]
+    pub tags: Tags,
+}
+
+/// Change state of an element in a diff query
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Modify,
+    Delete,
+}
+
+/// Data returned by [`Search::search_adiff`], an augmented diff of the
+/// elements matching the config between the two `diff_dates`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdiffResponse {
+    pub version: f64,
+    pub generator: String,
+    pub osm3s: OSMMetaData,
+    pub elements: Vec<AdiffElement>,
+    /// Note from the server, see [`OverpassResponse::remark`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remark: Option<String>,
+}
+
+/// Element of an [`AdiffResponse`] with its state at the start (`old`) and
+/// end (`new`) of the diff range. `old` is unset for a created element and
+/// `new` for a deleted one. A modified element may also have left the
+/// selection, e.g. by losing the tag, so compare the two to tell.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawAdiffElement")]
+pub struct AdiffElement {
+    pub action: Action,
+    pub old: Option<Element>,
+    pub new: Option<Element>,
+}
+
+/// Element of an augmented diff as returned by Overpass: modified and
+/// deleted elements carry `old` and `new` versions, a deleted one being
+/// marked `"visible": false`, while created ones are inlined
+#[derive(Deserialize)]
+struct RawAdiffElement {
+    action: Action,
+    old: Option<serde_json::Value>,
+    new: Option<serde_json::Value>,
+    #[serde(flatten)]
+    inline: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TryFrom<RawAdiffElement> for AdiffElement {
+    type Error = String;
+
+    fn try_from(raw: RawAdiffElement) -> Result<Self, Self::Error> {
+        let parse = |v: serde_json::Value| -> Result<Option<Element>, String> {
+            if v.get("visible") == Some(&serde_json::Value::Bool(false)) {
+                return Ok(None);
+            }
+            serde_json::from_value(v)
+                .map(Some)
+                .map_err(|e| e.to_string())
+        };
+        let (old, new) = match (raw.old, raw.new, raw.action) {
+            (None, None, Action::Create) => (None, parse(raw.inline.into())?),
+            (None, None, Action::Delete) => (parse(raw.inline.into())?, None),
+            (None, None, Action::Modify) => {
+                return Err("modified element without old and new versions".to_string())
+            }
+            (old, new, _) => (
+                old.map(parse).transpose()?.flatten(),
+                new.map(parse).transpose()?.flatten(),
+            ),
+        };
+        Ok(Self {
+            action: raw.action,
+            old,
+            new,
+        })
+    }
+}
+
+/// Elements of an `[out:xml]` response, see [`BoundingBox::search_xml`]
+#[cfg(feature = "xml")]
+#[derive(Debug, Clone, Default)]
+pub struct OsmXml {
+    pub version: Option<String>,
+    pub generator: Option<String>,
+    pub nodes: Vec<XmlNode>,
+    pub ways: Vec<XmlWay>,
+    pub relations: Vec<XmlRelation>,
+}
+
+/// Editing metadata attributes added by `out meta`
+#[cfg(feature = "xml")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XmlMeta {
+    pub version: Option<u32>,
+    pub timestamp: Option<String>,
+    pub changeset: Option<u64>,
+    pub user: Option<String>,
+    pub uid: Option<u64>,
+}
+
+/// Node of an [`OsmXml`] response
+#[cfg(feature = "xml")]
+#[derive(Debug, Clone, Default)]
+pub struct XmlNode {
+    pub id: u64,
+    pub lat: f64,
+    pub lon: f64,
+    pub tags: Tags,
+    pub meta: XmlMeta,
+}
+
+/// Way of an [`OsmXml`] response
+#[cfg(feature = "xml")]
+#[derive(Debug, Clone, Default)]
+pub struct XmlWay {
+    pub id: u64,
+    /// Ids of the member nodes, in order
+    pub nodes: Vec<u64>,
+    /// Center added by `out center`
+    pub center: Option<Coordinate>,
+    pub tags: Tags,
+    pub meta: XmlMeta,
+}
+
+/// Relation of an [`OsmXml`] response
+#[cfg(feature = "xml")]
+#[derive(Debug, Clone, Default)]
+pub struct XmlRelation {
+    pub id: u64,
+    pub members: Vec<Member>,
+    /// Center added by `out center`
+    pub center: Option<Coordinate>,
+    pub tags: Tags,
+    pub meta: XmlMeta,
+}
+
+#[cfg(feature = "xml")]
+impl OsmXml {
+    /// Parse an Overpass XML document
+    pub fn parse(doc: &str) -> Result<Self, Error> {
+        use crate::xml::Event;
+
+        fn attr<'a>(attrs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+            attrs
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        }
+        fn num<T: std::str::FromStr>(attrs: &[(String, String)], key: &str) -> Result<T, Error> {
+            attr(attrs, key)
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| Error::Xml(format!("missing or invalid `{}`", key)))
+        }
+        fn meta(attrs: &[(String, String)]) -> XmlMeta {
+            XmlMeta {
+                version: attr(attrs, "version").and_then(|v| v.parse().ok()),
+                timestamp: attr(attrs, "timestamp").map(str::to_string),
+                changeset: attr(attrs, "changeset").and_then(|v| v.parse().ok()),
+                user: attr(attrs, "user").map(str::to_string),
+                uid: attr(attrs, "uid").and_then(|v| v.parse().ok()),
+            }
+        }
+        fn center(attrs: &[(String, String)]) -> Result<Option<Coordinate>, Error> {
+            Ok(Some(Coordinate {
+                lat: num(attrs, "lat")?,
+                lon: num(attrs, "lon")?,
+            }))
+        }
+
+        let mut osm = OsmXml::default();
+        let mut node: Option<XmlNode> = None;
+        let mut way: Option<XmlWay> = None;
+        let mut relation: Option<XmlRelation> = None;
+        for event in crate::xml::parse(doc).map_err(Error::Xml)? {
+            match event {
+                Event::Start { name, attrs, empty } => match name.as_str() {
+                    "osm" => {
+                        osm.version = attr(&attrs, "version").map(str::to_string);
+                        osm.generator = attr(&attrs, "generator").map(str::to_string);
+                    }
+                    "node" => {
+                        let n = XmlNode {
+                            id: num(&attrs, "id")?,
+                            lat: num(&attrs, "lat")?,
+                            lon: num(&attrs, "lon")?,
+                            tags: Tags::new(),
+                            meta: meta(&attrs),
+                        };
+                        if empty {
+                            osm.nodes.push(n);
+                        } else {
+                            node = Some(n);
+                        }
+                    }
+                    "way" => {
+                        let w = XmlWay {
+                            id: num(&attrs, "id")?,
+                            meta: meta(&attrs),
+                            ..Default::default()
+                        };
+                        if empty {
+                            osm.ways.push(w);
+                        } else {
+                            way = Some(w);
+                        }
+                    }
+                    "relation" => {
+                        let r = XmlRelation {
+                            id: num(&attrs, "id")?,
+                            meta: meta(&attrs),
+                            ..Default::default()
+                        };
+                        if empty {
+                            osm.relations.push(r);
+                        } else {
+                            relation = Some(r);
+                        }
+                    }
+                    "tag" => {
+                        let tags = match (&mut node, &mut way, &mut relation) {
+                            (Some(n), _, _) => &mut n.tags,
+                            (_, Some(w), _) => &mut w.tags,
+                            (_, _, Some(r)) => &mut r.tags,
+                            _ => continue,
+                        };
+                        if let (Some(k), Some(v)) = (attr(&attrs, "k"), attr(&attrs, "v")) {
+                            tags.insert(k.to_string(), v.to_string());
+                        }
+                    }
+                    "nd" => {
+                        if let Some(w) = &mut way {
+                            w.nodes.push(num(&attrs, "ref")?);
+                        }
+                    }
+                    "member" => {
+                        if let Some(r) = &mut relation {
+                            let osm_type = match attr(&attrs, "type") {
+                                Some("node") => OsmType::Node,
+                                Some("way") => OsmType::Way,
+                                Some("relation") => OsmType::Relation,
+                                t => {
+                                    return Err(Error::Xml(format!("invalid member type {:?}", t)))
+                                }
+                            };
+                            r.members.push(Member {
+                                osm_type,
+                                id: num(&attrs, "ref")?,
+                                role: attr(&attrs, "role").unwrap_or_default().to_string(),
+                            });
+                        }
+                    }
+                    "center" => {
+                        if let Some(w) = &mut way {
+                            w.center = center(&attrs)?;
+                        } else if let Some(r) = &mut relation {
+                            r.center = center(&attrs)?;
+                        }
+                    }
+                    _ => {}
+                },
+                Event::End(name) => match name.as_str() {
+                    "node" => osm.nodes.extend(node.take()),
+                    "way" => osm.ways.extend(way.take()),
+                    "relation" => osm.relations.extend(relation.take()),
+                    _ => {}
+                },
+            }
+        }
+        Ok(osm)
+    }
+}
+
+/// Type of an OSM element
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum OsmType {
+    #[default]
+    Node,
+    Way,
+    Relation,
+}
+
+impl OsmType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OsmType::Node => "node",
+            OsmType::Way => "way",
+            OsmType::Relation => "relation",
+        }
+    }
+}
+
+/// Element types selected by a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElementType {
+    #[default]
+    Node,
+    Way,
+    Relation,
+    /// Nodes, ways and relations at once (`nwr`)
+    All,
+}
+
+impl ElementType {
+    /// Overpass QL statement selecting this type
+    fn selector(&self) -> &'static str {
+        match self {
+            ElementType::Node => "node",
+            ElementType::Way => "way",
+            ElementType::Relation => "relation",
+            ElementType::All => "nwr",
+        }
+    }
+}
+
+/// Defines an OSM node
+///
+/// Ways and relations returned by `out center` are parsed as nodes located
+/// at their center, with `osm_type` set accordingly.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawNode")]
+pub struct Node {
+    #[serde(rename = "type")]
+    pub osm_type: OsmType,
+    pub id: u64,
+    pub lat: f64,
+    pub lon: f64,
+    pub tags: Tags,
+    /// Set when the node was returned by a diff query
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<Action>,
+}
+
+/// Element as returned by Overpass, located either directly or by its center
+#[derive(Deserialize)]
+struct RawNode {
+    #[serde(rename = "type", default)]
+    osm_type: OsmType,
+    id: u64,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    center: Option<Coordinate>,
+    #[serde(default)]
+    tags: Tags,
+    #[serde(default)]
+    action: Option<Action>,
+}
+
+impl TryFrom<RawNode> for Node {
+    type Error = String;
+
+    fn try_from(raw: RawNode) -> Result<Self, Self::Error> {
+        let (lat, lon) = match (raw.lat, raw.lon, raw.center) {
+            (Some(lat), Some(lon), _) => (lat, lon),
+            (_, _, Some(c)) => (c.lat, c.lon),
+            _ => return Err(format!("element {} has no location", raw.id)),
+        };
+        Ok(Self {
+            osm_type: raw.osm_type,
+            id: raw.id,
+            lat,
+            lon,
+            tags: raw.tags,
+            action: raw.action,
+        })
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl OSMMetaData {
+    /// Parse `timestamp_osm_base` (e.g. `2023-10-01T12:00:00Z`) into a UTC datetime
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp_osm_base)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+impl OverpassResponse {
+    /// Append the elements of `others`, keeping the first occurrence of each
+    /// element by type and id, here or in `others`, in order. The metadata
+    /// of `self` is kept, and its remark unless it is not truncated and one
+    /// of `others` is, see [`OverpassResponse::is_truncated`].
+    pub fn merge(mut self, others: impl IntoIterator<Item = OverpassResponse>) -> Self {
+        let mut seen = HashSet::new();
+        self.elements.retain(|n| seen.insert((n.osm_type, n.id)));
+        for other in others {
+            if self.remark.is_none() || other.is_truncated() && !self.is_truncated() {
+                self.remark = other.remark;
+            }
+            self.elements.extend(
+                other
+                    .elements
+                    .into_iter()
+                    .filter(|n| seen.insert((n.osm_type, n.id))),
+            );
+        }
+        self
+    }
+
+    /// The server's remark, if any
+    pub fn warning(&self) -> Option<&str> {
+        self.remark.as_deref()
+    }
+
+    /// Whether the server stopped early, e.g. on a timeout or the memory
+    /// limit, and sent partial results with a 200 status
+    pub fn is_truncated(&self) -> bool {
+        is_truncated(self.remark.as_deref())
+    }
+
+    /// Write the nodes as the text of a SQL script creating `table` with
+    /// `id`, `lat`, `lon` and a JSON `tags` column and inserting one row per
+    /// node. This does not write a SQLite database itself, as the crate has
+    /// no SQLite binding: the script must be loaded with
+    /// `sqlite3 nodes.db < nodes.sql`.
+    pub fn write_sql_script<W: Write>(&self, mut w: W, table: &str) -> io::Result<()> {
+        if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid table name `{}`", table),
+            ));
+        }
+        writeln!(w, "BEGIN TRANSACTION;")?;
+        writeln!(
+            w,
+            "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, lat REAL NOT NULL, lon REAL NOT NULL, tags TEXT NOT NULL);",
+            table
+        )?;
+        for n in &self.elements {
+            let tags = serde_json::to_string(&n.tags)?.replace('\'', "''");
+            writeln!(
+                w,
+                "INSERT OR REPLACE INTO {} (id, lat, lon, tags) VALUES ({}, {}, {}, '{}');",
+                table, n.id, n.lat, n.lon, tags
+            )?;
+        }
+        writeln!(w, "COMMIT;")
+    }
+
+    /// Write the nodes as JSON Lines, one serialized [`Node`] per line, so
+    /// large exports can be streamed to a file without building one array
+    pub fn write_ndjson<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for n in &self.elements {
+            serde_json::to_writer(&mut w, n)?;
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// GeoJSON `FeatureCollection` with a `Point` feature per node, whose
+    /// properties are the tags plus the OSM `@id` and `@type`
+    pub fn to_geojson(&self) -> serde_json::Value {
+        let features: Vec<serde_json::Value> = self
+            .elements
+            .iter()
+            .map(|n| {
+                let mut props = serde_json::Map::new();
+                props.insert("@id".to_string(), n.id.into());
+                props.insert("@type".to_string(), n.osm_type.as_str().into());
+                for (k, v) in &n.tags {
+                    props.insert(k.clone(), v.clone().into());
+                }
+                serde_json::json!({
+                    "type": "Feature",
+                    "id": n.osm_ref(),
+                    "geometry": {"type": "Point", "coordinates": [n.lon, n.lat]},
+                    "properties": props,
+                })
+            })
+            .collect();
+        serde_json::json!({"type": "FeatureCollection", "features": features})
+    }
+
+    /// Nodes without a `key` tag, e.g. restaurants lacking `opening_hours`
+    pub fn missing_tag(&self, key: &str) -> Vec<&Node> {
+        self.elements
+            .iter()
+            .filter(|n| !n.tags.contains_key(key))
+            .collect()
+    }
+
+    /// Nodes tagged `key=value`, e.g. `wheelchair=yes`, to refine a broad
+    /// query client-side
+    pub fn filter_by_tag<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+    ) -> impl Iterator<Item = &'a Node> + 'a {
+        self.filter_by(move |n| n.tag(key) == Some(value))
+    }
+
+    /// Nodes a diff query reported with `action`, see
+    /// [`Config::diff_dates`]
+    pub fn changes(&self, action: Action) -> impl Iterator<Item = &Node> + '_ {
+        self.filter_by(move |n| n.action == Some(action))
+    }
+
+    /// Nodes for which `pred` returns true
+    pub fn filter_by<'a, F>(&'a self, pred: F) -> impl Iterator<Item = &'a Node> + 'a
+    where
+        F: Fn(&Node) -> bool + 'a,
+    {
+        self.elements.iter().filter(move |n| pred(n))
+    }
+
+    /// Extent of the nodes grown by `margin_km` on every side, e.g. to offer
+    /// "search this area" around the current results
+    pub fn coverage_box(&self, margin_km: f64) -> Option<BoundingBox> {
+        let first = self.elements.first()?;
+        let mut b = BoundingBox {
+            xmin: first.lon,
+            ymin: first.lat,
+            xmax: first.lon,
+            ymax: first.lat,
+        };
+        for n in &self.elements[1..] {
+            b.xmin = b.xmin.min(n.lon);
+            b.ymin = b.ymin.min(n.lat);
+            b.xmax = b.xmax.max(n.lon);
+            b.ymax = b.ymax.max(n.lat);
+        }
+        // Widen longitudes using the edge nearest a pole, where degrees are shortest
+        let edge = b.ymin.abs().max(b.ymax.abs()).to_radians();
+        let dy = (margin_km * 1000.0 / wgs84_earth_radius(edge)).to_degrees();
+        let dx = dy / edge.cos().max(1e-6);
+        Some(BoundingBox {
+            xmin: (b.xmin - dx).max(-180.0),
+            ymin: (b.ymin - dy).max(-90.0),
+            xmax: (b.xmax + dx).min(180.0),
+            ymax: (b.ymax + dy).min(90.0),
+        })
+    }
+
+    /// Node closest to `(lat, lon)` by great-circle distance
+    pub fn nearest(&self, lat: f64, lon: f64) -> Option<&Node> {
+        self.elements.iter().min_by(|a, b| {
+            haversine_km(lat, lon, a.lat, a.lon).total_cmp(&haversine_km(lat, lon, b.lat, b.lon))
+        })
+    }
+
+    /// Bucket nodes into `rings` concentric rings of width `ring_km` around
+    /// `(lat, lon)` by straight-line distance. Ring `i` holds the nodes from
+    /// `i * ring_km` up to, but excluding, `(i + 1) * ring_km`; nodes beyond
+    /// the outermost ring are left out.
+    pub fn distance_rings(
+        &self,
+        lat: f64,
+        lon: f64,
+        ring_km: f64,
+        rings: usize,
+    ) -> Vec<Vec<&Node>> {
+        let mut out = vec![Vec::new(); rings];
+        if ring_km <= 0.0 {
+            return out;
+        }
+        for node in &self.elements {
+            let ring = (haversine_km(lat, lon, node.lat, node.lon) / ring_km) as usize;
+            if let Some(bucket) = out.get_mut(ring) {
+                bucket.push(node);
+            }
+        }
+        out
+    }
+}
+
+impl TagsResponse {
+    /// The server's remark, if any
+    pub fn warning(&self) -> Option<&str> {
+        self.remark.as_deref()
+    }
+
+    /// Whether the server sent partial results, see
+    /// [`OverpassResponse::is_truncated`]
+    pub fn is_truncated(&self) -> bool {
+        is_truncated(self.remark.as_deref())
+    }
+}
+
+impl ElementsResponse {
+    /// The server's remark, if any
+    pub fn warning(&self) -> Option<&str> {
+        self.remark.as_deref()
+    }
+
+    /// Whether the server sent partial results, see
+    /// [`OverpassResponse::is_truncated`]
+    pub fn is_truncated(&self) -> bool {
+        is_truncated(self.remark.as_deref())
+    }
+}
+
+/// Whether `remark` reports a runtime error, after which Overpass sends what
+/// it has so far
+fn is_truncated(remark: Option<&str>) -> bool {
+    remark.is_some_and(|r| r.contains("runtime error"))
+}
+
+impl Node {
+    /// Type and id as used in OSM paths, e.g. `node/123`
+    pub fn osm_ref(&self) -> String {
+        format!("{}/{}", self.osm_type.as_str(), self.id)
+    }
+
+    /// Link to the element on openstreetmap.org
+    pub fn osm_url(&self) -> String {
+        format!("https://www.openstreetmap.org/{}", self.osm_ref())
+    }
+
+    /// Value of tag `key`
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.tag("name")
+    }
+
+    pub fn amenity(&self) -> Option<&str> {
+        self.tag("amenity")
+    }
+
+    pub fn shop(&self) -> Option<&str> {
+        self.tag("shop")
+    }
+
+    pub fn cuisine(&self) -> Option<&str> {
+        self.tag("cuisine")
+    }
+
+    pub fn brand(&self) -> Option<&str> {
+        self.tag("brand")
+    }
+
+    pub fn operator(&self) -> Option<&str> {
+        self.tag("operator")
+    }
+
+    /// Raw `opening_hours` value, see `is_open_at` to evaluate it
+    pub fn opening_hours(&self) -> Option<&str> {
+        self.tag("opening_hours")
+    }
+
+    pub fn wheelchair(&self) -> Option<&str> {
+        self.tag("wheelchair")
+    }
+
+    /// `website`, or `contact:website` when only that is set
+    pub fn website(&self) -> Option<&str> {
+        self.tag("website").or_else(|| self.tag("contact:website"))
+    }
+
+    /// `phone`, or `contact:phone` when only that is set
+    pub fn phone(&self) -> Option<&str> {
+        self.tag("phone").or_else(|| self.tag("contact:phone"))
+    }
+
+    /// Whether the `opening_hours` tag says the place is open at `datetime`,
+    /// local to the place. `None` when the tag is missing or can't be
+    /// parsed, or the hours are marked unknown at that time.
+    #[cfg(feature = "opening-hours")]
+    pub fn is_open_at(&self, datetime: chrono::NaiveDateTime) -> Option<bool> {
+        use opening_hours::{OpeningHours, RuleKind};
+
+        let hours = OpeningHours::parse(self.opening_hours()?).ok()?;
+        match hours.state(datetime).ok()? {
+            RuleKind::Open => Some(true),
+            RuleKind::Closed => Some(false),
+            RuleKind::Unknown => None,
+        }
+    }
+
+    /// First `(key, value)` present among `PRIMARY_KEYS`, e.g. `("amenity", "cafe")`
+    pub fn primary_category(&self) -> Option<(String, String)> {
+        PRIMARY_KEYS
+            .iter()
+            .find_map(|k| self.tags.get(*k).map(|v| (k.to_string(), v.clone())))
+    }
+
+    /// Deprecated tags used by this node, as listed in `DEPRECATED_TAGS`
+    pub fn deprecated_tags(&self) -> Vec<&str> {
+        DEPRECATED_TAGS
+            .iter()
+            .copied()
+            .filter(|t| match t.split_once('=') {
+                Some((k, v)) => self.tags.get(k).is_some_and(|tv| tv == v),
+                None => self.tags.contains_key(*t),
+            })
+            .collect()
+    }
+}
+
+/// Global query settings for a search covering `area_km2`, including the
+/// `[timeout:N]` chosen for it. An infinite area, for searches of unknown
+/// size, gets the full `timeout`.
+fn settings_for_area(config: &Config, area_km2: f64) -> String {
+    let timeout = if config.auto_timeout {
+        scaled_timeout(area_km2, config.timeout)
+    } else {
+        config.timeout
+    };
+    settings_with_timeout(config, Some(timeout))
+}
+
+/// Global query settings, e.g. `[out:json][timeout:25][diff:"a","b"];`
+fn settings_with_timeout(config: &Config, timeout: Option<u8>) -> String {
+    let mut s = match &config.format {
+        OutputFormat::Json => "[out:json]".to_string(),
+        OutputFormat::Csv(cols) => {
+            let cols: Vec<String> = cols
+                .iter()
+                .map(|c| {
+                    // Special fields such as `::id` are unquoted, so only
+                    // plain names are passed through as is
+                    let special = c.strip_prefix("::").is_some_and(|f| {
+                        !f.is_empty() && f.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+                    });
+                    if special {
+                        c.clone()
+                    } else {
+                        format!("\"{}\"", ql_escape(c))
+                    }
+                })
+                .collect();
+            format!("[out:csv({})]", cols.join(","))
+        }
+        OutputFormat::Xml => "[out:xml]".to_string(),
+    };
+    if let Some(timeout) = timeout {
+        s.push_str(&format!("[timeout:{}]", timeout));
+    }
+    if let Some(maxsize) = config.maxsize {
+        s.push_str(&format!("[maxsize:{}]", maxsize));
+    }
+    if let Some((from, to)) = &config.diff_dates {
+        let mode = if config.augmented_diff {
+            "adiff"
+        } else {
+            "diff"
+        };
+        s.push_str(&format!(
+            "[{}:\"{}\",\"{}\"]",
+            mode,
+            ql_escape(from),
+            ql_escape(to)
+        ));
+    }
+    s.push(';');
+    s
+}
+
+/// Whether `s` is a UTC timestamp of the form `2023-01-01T00:00:00Z`
+fn is_timestamp(s: &str) -> bool {
+    let b = s.as_bytes();
+    let digits = |r: std::ops::Range<usize>| b[r].iter().all(u8::is_ascii_digit);
+    let num = |r: std::ops::Range<usize>| s[r].parse::<u32>().unwrap_or(u32::MAX);
+    b.len() == 20
+        && digits(0..4)
+        && b[4] == b'-'
+        && digits(5..7)
+        && b[7] == b'-'
+        && digits(8..10)
+        && b[10] == b'T'
+        && digits(11..13)
+        && b[13] == b':'
+        && digits(14..16)
+        && b[16] == b':'
+        && digits(17..19)
+        && b[19] == b'Z'
+        && (1..=12).contains(&num(5..7))
+        && (1..=31).contains(&num(8..10))
+        && num(11..13) <= 23
+        && num(14..16) <= 59
+        && num(17..19) <= 60
+}
+
+/// Timeout in seconds for a query covering `area_km2`: [`MIN_TIMEOUT`] plus
+/// one second per 100 km², rounded and capped at `max`. A `max` below the floor is
+/// returned unchanged, as is `max` for an area that is not finite.
+pub fn scaled_timeout(area_km2: f64, max: u8) -> u8 {
+    if !area_km2.is_finite() {
+        return max;
+    }
+    let scaled = MIN_TIMEOUT as f64 + area_km2.max(0.0) / KM2_PER_TIMEOUT_SECOND;
+    scaled
+        .round()
+        .min(max as f64)
+        .max(MIN_TIMEOUT.min(max) as f64) as u8
+}
+
+/// Format a number for QL in plain decimal notation with at most 8
+/// decimals (about 1 mm), dropping trailing zeros
+fn coord(v: f64) -> String {
+    let s = format!("{:.8}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+/// Output statement ending a query, adding metadata to XML output
+fn out(config: &Config) -> String {
+    let detail = match (&config.format, config.output) {
+        (OutputFormat::Xml, OutputDetail::Center) => "meta center",
+        (OutputFormat::Xml, OutputDetail::Body) => "meta",
+        (_, d) => d.keyword(),
+    };
+    let qt = if config.quadtile { " qt" } else { "" };
+    let limit = config.limit.map(|n| format!(" {}", n)).unwrap_or_default();
+    format!("out {}{}{};", detail, qt, limit)
+}
+
+/// Geocentric WGS-84 radius in metres at latitude `lat` in radians
+fn wgs84_earth_radius(lat: f64) -> f64 {
+    let an = WGS84A * WGS84A * lat.cos();
+    let bn = WGS84B * WGS84B * lat.sin();
+    let ad = WGS84A * lat.cos();
+    let bd = WGS84B * lat.sin();
+    ((an * an + bn * bn) / (ad * ad + bd * bd)).sqrt()
+}
+
+/// WGS-84 radius in metres at latitude `lat` in degrees, linearly
+/// interpolated from a table computed once per whole degree. Within 2 m of
+/// the exact value (out of ~6 371 km), trading that for no trig per call.
+pub fn wgs84_earth_radius_fast(lat: f64) -> f64 {
+    static TABLE: OnceLock<[f64; 91]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut t = [0.0; 91];
+        for (deg, r) in t.iter_mut().enumerate() {
+            *r = wgs84_earth_radius((deg as f64).to_radians());
+        }
+        t
+    });
+    let lat = lat.abs().min(90.0);
+    let i = (lat as usize).min(89);
+    let frac = lat - i as f64;
+    table[i] + (table[i + 1] - table[i]) * frac
+}
+
+/// Great-circle distance in km between two points given in degrees, using
+/// the WGS-84 radius at their mean latitude
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (p1, p2) = (lat1.to_radians(), lat2.to_radians());
+    let dp = p2 - p1;
+    let dl = (lon2 - lon1).to_radians();
+    let h = (dp / 2.0).sin().powi(2) + p1.cos() * p2.cos() * (dl / 2.0).sin().powi(2);
+    let r = wgs84_earth_radius((p1 + p2) / 2.0);
+    2.0 * r * h.sqrt().asin() / 1000.0
+}
+
+impl Coordinate {
+    /// Great-circle distance to `other` in km, see [`haversine_km`]
+    pub fn distance_km(&self, other: &Coordinate) -> f64 {
+        haversine_km(self.lat, self.lon, other.lat, other.lon)
+    }
+
+    /// Point halfway to `other` along the great circle
+    pub fn midpoint(&self, other: &Coordinate) -> Coordinate {
+        let (p1, p2) = (self.lat.to_radians(), other.lat.to_radians());
+        let l1 = self.lon.to_radians();
+        let dl = (other.lon - self.lon).to_radians();
+        let bx = p2.cos() * dl.cos();
+        let by = p2.cos() * dl.sin();
+        let lat = (p1.sin() + p2.sin()).atan2(((p1.cos() + bx).powi(2) + by * by).sqrt());
+        let lon = l1 + by.atan2(p1.cos() + bx);
+        Coordinate {
+            lat: lat.to_degrees(),
+            lon: (lon.to_degrees() + 540.0) % 360.0 - 180.0,
+        }
+    }
+
+    /// Initial bearing towards `other` along the great circle, in degrees
+    /// clockwise from north in `[0, 360)`
+    pub fn bearing_to(&self, other: &Coordinate) -> f64 {
+        let (p1, p2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dl = (other.lon - self.lon).to_radians();
+        let y = dl.sin() * p2.cos();
+        let x = p1.cos() * p2.sin() - p1.sin() * p2.cos() * dl.cos();
+        (y.atan2(x).to_degrees() + 360.0) % 360.0
+    }
+}
+
+/// Where to look for elements, e.g. a [`BoundingBox`] or an [`Around`]
+/// radius. The search methods are provided on top of [`Search::build_query`]
+/// and check [`Search::validate`] before sending anything.
+pub trait Search: Sync {
+    /// Check the search is well formed, e.g. that its coordinates are in
+    /// range
+    fn validate(&self) -> Result<(), Error>;
+
+    /// Overpass QL sent by the search methods
+    fn build_query(&self, config: &Config) -> String;
+
+    /// Asynchronously search for elements matching `config`
+    fn search(
+        &self,
+        config: &Config,
+    ) -> impl Future<Output = Result<OverpassResponse, Error>> + Send {
+        async move {
+            self.validate()?;
+            send(config, self.build_query(config)).await
+        }
+    }
+
+    /// Asynchronously search as with `search`, giving up with
+    /// [`Error::Cancelled`] as soon as `token` is cancelled
+    fn search_with_cancel(
+        &self,
+        config: &Config,
+        token: CancellationToken,
+    ) -> impl Future<Output = Result<OverpassResponse, Error>> + Send {
+        cancellable(token, self.search(config))
+    }
+
+    /// Asynchronously search for the raw CSV body, failing with
+    /// [`Error::InvalidConfig`] unless `config.format` is
+    /// [`OutputFormat::Csv`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{BoundingBox, Config, OutputFormat, Search};
+    /// #[tokio::main]
+    /// async fn main() {
+    ///   let c: Config = Config {
+    ///       key: "amenity".to_string(),
+    ///       val: "cafe".to_string(),
+    ///       format: OutputFormat::Csv(vec!["::id".to_string(), "name".to_string()]),
+    ///       ..Default::default()
+    ///   };
+    ///
+    ///   let b: BoundingBox = BoundingBox {
+    ///       xmin: -0.7690429687500001,
+    ///       ymin: 51.305219521963295,
+    ///       xmax: 0.5273437500000064,
+    ///       ymax: 51.82219818336938,
+    ///   };
+    ///
+    ///   let csv = b.search_csv(&c).await.expect("failed query");
+    /// }
+    /// ```
+    fn search_csv(&self, config: &Config) -> impl Future<Output = Result<String, Error>> + Send {
+        async move {
+            if !matches!(config.format, OutputFormat::Csv(_)) {
+                return Err(Error::InvalidConfig(
+                    "search_csv needs OutputFormat::Csv".to_string(),
+                ));
+            }
+            self.validate()?;
+            send_text(config, self.build_query(config)).await
+        }
+    }
+
+    /// Asynchronously search as with `search`, for use with an
+    /// [`OutputDetail`] that omits locations such as `Tags`
+    fn search_tags(
+        &self,
+        config: &Config,
+    ) -> impl Future<Output = Result<TagsResponse, Error>> + Send {
+        async move {
+            self.validate()?;
+            send_as(config, self.build_query(config)).await
+        }
+    }
+
+    /// Asynchronously search as with `search`, keeping ways and relations
+    /// apart from nodes, see [`Element`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{BoundingBox, Config, Element, ElementType, Search};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let c = Config::builder()
+    ///         .tag("amenity", "cafe")
+    ///         .element_type(ElementType::All)
+    ///         .build()
+    ///         .unwrap();
+    ///     let b = BoundingBox { xmin: -0.13, ymin: 51.50, xmax: -0.12, ymax: 51.51 };
+    ///     let resp = b.search_elements(&c).await.unwrap();
+    ///     for e in &resp.elements {
+    ///         if let Element::Way { id, nodes, .. } = e {
+    ///             println!("way {} has {} nodes", id, nodes.len());
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn search_elements(
+        &self,
+        config: &Config,
+    ) -> impl Future<Output = Result<ElementsResponse, Error>> + Send {
+        async move {
+            self.validate()?;
+            send_as(config, self.build_query(config)).await
+        }
+    }
+
+    /// Asynchronously search for the untyped JSON response, keeping fields
+    /// such as `bounds` or `geometry` that the typed responses drop
+    fn search_value(
+        &self,
+        config: &Config,
+    ) -> impl Future<Output = Result<serde_json::Value, Error>> + Send {
+        async move {
+            self.validate()?;
+            send_as(config, self.build_query(config)).await
+        }
+    }
+
+    /// Asynchronously search for the changes between `config.diff_dates`
+    /// as an augmented diff, see [`AdiffElement`]. Fails with
+    /// [`Error::InvalidConfig`] when no dates are set.
+    fn search_adiff(
+        &self,
+        config: &Config,
+    ) -> impl Future<Output = Result<AdiffResponse, Error>> + Send {
+        async move {
+            if config.diff_dates.is_none() {
+                return Err(Error::InvalidConfig(
+                    "search_adiff needs diff_dates".to_string(),
+                ));
+            }
+            self.validate()?;
+            let config = Config {
+                augmented_diff: true,
+                ..config.clone()
+            };
+            send_as(&config, self.build_query(&config)).await
+        }
+    }
+}
+
+impl BoundingBox {
+    /// Construct a bounding box extending `dkm` km north, south, east and
+    /// west of the point at `lat`, `lon` in degrees, see
+    /// [`BoundingBox::from_center`]
+    pub fn from_point(lat: f64, lon: f64, dkm: f64) -> Self {
+        Self::from_center(lat, lon, 2.0 * dkm, 2.0 * dkm)
+    }
+
+    /// Construct a bounding box `width_km` wide and `height_km` tall
+    /// centered on the point at `lat`, `lon` in degrees
+    ///
+    /// Distances use the WGS-84 radius at `lat` on a sphere, which is within
+    /// about 0.5% of the ellipsoidal distance. Latitudes are clamped to ±90°,
+    /// and once the box reaches a pole it spans every longitude, as all of
+    /// them are then within half its width of the point.
+    pub fn from_center(lat: f64, lon: f64, width_km: f64, height_km: f64) -> Self {
+        let erad = wgs84_earth_radius(lat.to_radians());
+        let dy = (height_km * 500.0 / erad).to_degrees();
+        let (ymin, ymax) = (lat - dy, lat + dy);
+        if ymin <= -90.0 || ymax >= 90.0 {
+            return Self {
+                xmin: -180.0,
+                ymin: ymin.max(-90.0),
+                xmax: 180.0,
+                ymax: ymax.min(90.0),
+            };
+        }
+
+        let prad = erad * lat.to_radians().cos();
+        let dx = (width_km * 500.0 / prad).to_degrees();
+        let (xmin, xmax) = if dx < 180.0 {
+            (lon - dx, lon + dx)
+        } else {
+            (-180.0, 180.0)
+        };
+        Self {
+            xmin,
+            ymin,
+            xmax,
+            ymax,
+        }
+    }
+
+    /// Construct the bounding box of a `width_px` x `height_px` Web Mercator
+    /// viewport centered on `center` at slippy map `zoom`
+    ///
+    /// The center's longitude is wrapped into [-180, 180). A box cannot
+    /// cross the antimeridian, so a viewport that does is cut off at ±180°,
+    /// and one wider than the world spans every longitude.
+    pub fn from_viewport(center: Coordinate, width_px: u32, height_px: u32, zoom: u8) -> Self {
+        let world = TILE_SIZE * 2f64.powi(zoom as i32);
+        let lon = (center.lon + 180.0).rem_euclid(360.0) - 180.0;
+        let lat = center.lat.to_radians();
+        let cy = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0 * world;
+
+        let to_lat = |y: f64| {
+            (std::f64::consts::PI * (1.0 - 2.0 * y / world))
+                .sinh()
+                .atan()
+                .to_degrees()
+        };
+        let (hw, hh) = (width_px as f64 / 2.0, height_px as f64 / 2.0);
+        let dx = hw / world * 360.0;
+
+        Self {
+            xmin: (lon - dx).max(-180.0),
+            ymin: to_lat(cy + hh),
+            xmax: (lon + dx).min(180.0),
+            ymax: to_lat(cy - hh),
+        }
+    }
+
+    /// Check the box is well ordered and within valid degree ranges
+    pub fn validate(&self) -> Result<(), BBoxError> {
+        let coords = [self.xmin, self.ymin, self.xmax, self.ymax];
+        if coords.iter().any(|c| !c.is_finite()) {
+            return Err(BBoxError::NotFinite);
+        }
+        for lon in [self.xmin, self.xmax] {
+            if !(-180.0..=180.0).contains(&lon) {
+                return Err(BBoxError::LongitudeOutOfRange(lon));
+            }
+        }
+        for lat in [self.ymin, self.ymax] {
+            if !(-90.0..=90.0).contains(&lat) {
+                return Err(BBoxError::LatitudeOutOfRange(lat));
+            }
+        }
+        if self.xmin >= self.xmax || self.ymin >= self.ymax {
+            return Err(BBoxError::Inverted);
+        }
+        Ok(())
+    }
+
+    /// Asynchronously search for elements within the bounding box by tag as
+    /// XML, which unlike JSON carries the `version`, `timestamp`, `user`
+    /// and `changeset` of every element
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{BoundingBox, Config};
+    /// #[tokio::main]
+    /// async fn main() {
+    ///   let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+    ///   let b = BoundingBox { xmin: -0.13, ymin: 51.50, xmax: -0.12, ymax: 51.51 };
+    ///
+    ///   let osm = b.search_xml(&c).await.expect("failed query");
+    ///   for n in osm.nodes {
+    ///       println!("{} last edited by {:?}", n.id, n.meta.user);
+    ///   }
+    /// }
+    /// ```
+    #[cfg(feature = "xml")]
+    pub async fn search_xml(&self, config: &Config) -> Result<OsmXml, Error> {
+        self.validate()?;
+        let config = Config {
+            format: OutputFormat::Xml,
+            ..config.clone()
+        };
+        let body = send_text(&config, self.build_query(&config)).await?;
+        OsmXml::parse(&body)
+    }
+
+    /// Search the box and write the nodes to `path` as a GeoJSON
+    /// `FeatureCollection`, see [`OverpassResponse::to_geojson`]
+    pub async fn search_to_geojson_file(
+        &self,
+        config: &Config,
+        path: impl AsRef<Path>,
+    ) -> Result<OverpassResponse, Error> {
+        let resp = self.search(config).await?;
+        let file = io::BufWriter::new(File::create(path)?);
+        serde_json::to_writer(file, &resp.to_geojson())?;
+        Ok(resp)
+    }
+
+    /// Approximate area of the box in km²
+    pub fn area_km2(&self) -> f64 {
+        let (width_km, height_km) = self.size_km();
+        width_km * height_km
+    }
+
+    /// Width and height in km, measured at the box's mean latitude
+    fn size_km(&self) -> (f64, f64) {
+        let lat = ((self.ymin + self.ymax) / 2.0).to_radians();
+        let r = wgs84_earth_radius(lat) / 1000.0;
+        let height_km = (self.ymax - self.ymin).to_radians() * r;
+        let width_km = (self.xmax - self.xmin).to_radians() * r * lat.cos();
+        (width_km, height_km)
+    }
+
+    /// Rows and columns of a grid splitting the box into tiles expected to
+    /// hold at most `max_elements` each, given an estimated density of
+    /// elements per km². Tiles are kept roughly square.
+    pub fn plan_tiling(
+        &self,
+        max_elements: usize,
+        estimated_density_per_km2: f64,
+    ) -> (usize, usize) {
+        let (width_km, height_km) = self.size_km();
+        let expected = width_km * height_km * estimated_density_per_km2;
+        if max_elements == 0 || !expected.is_finite() || expected <= max_elements as f64 {
+            return (1, 1);
+        }
+        let tiles = (expected / max_elements as f64).ceil();
+        let cols = (tiles * width_km / height_km).sqrt().ceil().max(1.0);
+        let rows = (tiles / cols).ceil().max(1.0);
+        (rows as usize, cols as usize)
+    }
+
+    /// Split the box into a grid of tiles at most `max_tile_deg` degrees on
+    /// each side, row by row from the south-west corner
+    pub fn tiles(&self, max_tile_deg: f64) -> Vec<BoundingBox> {
+        if max_tile_deg.is_nan() || max_tile_deg <= 0.0 {
+            return vec![self.clone()];
+        }
+        // Allow for rounding so a 0.3 degree box splits into 3 tiles of 0.1
+        let count = |span: f64| ((span / max_tile_deg) - 1e-9).ceil().max(1.0) as usize;
+        let (nx, ny) = (count(self.xmax - self.xmin), count(self.ymax - self.ymin));
+        let dx = (self.xmax - self.xmin) / nx as f64;
+        let dy = (self.ymax - self.ymin) / ny as f64;
+        let mut tiles = Vec::with_capacity(nx * ny);
+        for j in 0..ny {
+            for i in 0..nx {
+                tiles.push(BoundingBox {
+                    xmin: self.xmin + dx * i as f64,
+                    ymin: self.ymin + dy * j as f64,
+                    xmax: if i + 1 == nx {
+                        self.xmax
+                    } else {
+                        self.xmin + dx * (i + 1) as f64
+                    },
+                    ymax: if j + 1 == ny {
+                        self.ymax
+                    } else {
+                        self.ymin + dy * (j + 1) as f64
+                    },
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Search the box one tile at a time, see [`BoundingBox::tiles`],
+    /// yielding each tile's response as it arrives so large extractions can
+    /// be processed without holding every result in memory. Nodes on a
+    /// shared tile edge are returned by both tiles.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use futures_util::StreamExt;
+    /// use osm_rs::overpass::{BoundingBox, Config};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+    ///     let b = BoundingBox { xmin: -0.2, ymin: 51.4, xmax: 0.1, ymax: 51.6 };
+    ///     let mut tiles = Box::pin(b.search_tiled_stream(&c, 0.1));
+    ///     while let Some(resp) = tiles.next().await {
+    ///         println!("{} cafes", resp.unwrap().elements.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn search_tiled_stream<'a>(
+        &self,
+        config: &'a Config,
+        max_tile_deg: f64,
+    ) -> impl Stream<Item = Result<OverpassResponse, Error>> + 'a {
+        stream::iter(self.tiles(max_tile_deg)).then(move |b| async move {
+            let res = b.search(config).await;
+            if let Some(delay) = config.delay_between {
+                tokio::time::sleep(delay).await;
+            }
+            res
+        })
+    }
+
+    /// Search the box as tiles of at most `max_tile_deg` degrees, running up
+    /// to `concurrency` tile queries at once. The responses of the tiles
+    /// that succeeded are merged, without the duplicates found on shared
+    /// edges, and the tiles that failed are returned with their error so
+    /// they can be retried.
+    pub async fn search_tiled(
+        &self,
+        config: &Config,
+        max_tile_deg: f64,
+        concurrency: usize,
+    ) -> (OverpassResponse, Vec<(BoundingBox, Error)>) {
+        let tiles = self.tiles(max_tile_deg);
+        let results = search_all(&tiles, config, concurrency).await;
+        let mut found = Vec::new();
+        let mut failed = Vec::new();
+        for (tile, res) in tiles.into_iter().zip(results) {
+            match res {
+                Ok(resp) => found.push(resp),
+                Err(e) => failed.push((tile, e)),
+            }
+        }
+        let mut found = found.into_iter();
+        let merged = found
+            .next()
+            .map(|first| first.merge(found))
+            .unwrap_or_default();
+        (merged, failed)
+    }
+}
+
+/// Searches for nodes within the bounding box by tag
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{BoundingBox, Config, Search};
+/// #[tokio::main]
+/// async fn main() {
+///   let c: Config = Config {
+///       url: "https://overpass-api.de/api/interpreter".to_string(),
+///       timeout: 25,
+///       key: "amenity".to_string(),
+///       val: "cafe".to_string(),
+///       ..Default::default()
+///   };
+///
+///   let b: BoundingBox = BoundingBox {
+///       xmin: -0.7690429687500001,
+///       ymin: 51.305219521963295,
+///       xmax: 0.5273437500000064,
+///       ymax: 51.82219818336938,
+///   };
+///
+///   let resp = b.search(&c).await.expect("failed query");
+/// }
+/// ```
+impl Search for BoundingBox {
+    /// Check the box as [`BoundingBox::validate`] does
+    fn validate(&self) -> Result<(), Error> {
+        Ok(BoundingBox::validate(self)?)
+    }
+
+    /// Overpass QL sent by the search methods
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{BoundingBox, Config, Search};
+    ///
+    /// let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+    /// let b = BoundingBox { xmin: -0.2, ymin: 51.4, xmax: 0.1, ymax: 51.6 };
+    /// assert_eq!(
+    ///     b.build_query(&c),
+    ///     "[out:json][timeout:15];node[\"amenity\"=\"cafe\"](51.4,-0.2,51.6,0.1);out center;"
+    /// );
+    /// ```
+    fn build_query(&self, config: &Config) -> String {
+        format!(
+            "{}{}{}({},{},{},{});{}",
+            settings_for_area(config, self.area_km2()),
+            config.element_type.selector(),
+            tag_selectors(config),
+            coord(self.ymin),
+            coord(self.xmin),
+            coord(self.ymax),
+            coord(self.xmax),
+            out(config)
+        )
+    }
+}
+
+impl Around {
+    /// Area of the circle in km²
+    pub fn area_km2(&self) -> f64 {
+        let r = self.radius_m / 1000.0;
+        std::f64::consts::PI * r * r
+    }
+}
+
+/// Searches for nodes within the radius by tag
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{Around, Config, Search};
+/// #[tokio::main]
+/// async fn main() {
+///   let c: Config = Config {
+///       url: "https://overpass-api.de/api/interpreter".to_string(),
+///       timeout: 25,
+///       key: "amenity".to_string(),
+///       val: "cafe".to_string(),
+///       ..Default::default()
+///   };
+///
+///   let a = Around {
+///       lat: 51.5072,
+///       lon: -0.1276,
+///       radius_m: 500.0,
+///   };
+///
+///   let resp = a.search(&c).await.expect("failed query");
+/// }
+/// ```
+impl Search for Around {
+    /// Check that the center is within latitude and longitude range and the
+    /// radius is positive
+    fn validate(&self) -> Result<(), Error> {
+        if !((-90.0..=90.0).contains(&self.lat) && (-180.0..=180.0).contains(&self.lon)) {
+            return Err(Error::InvalidQuery(format!(
+                "center ({}, {}) out of range",
+                self.lat, self.lon
+            )));
+        }
+        if !(self.radius_m.is_finite() && self.radius_m > 0.0) {
+            return Err(Error::InvalidQuery(format!(
+                "radius must be positive, got {}",
+                self.radius_m
+            )));
+        }
+        Ok(())
+    }
+
+    /// Overpass QL sent by the search methods
+    fn build_query(&self, config: &Config) -> String {
+        format!(
+            "{}{}{}(around:{},{},{});{}",
+            settings_for_area(config, self.area_km2()),
+            config.element_type.selector(),
+            tag_selectors(config),
+            coord(self.radius_m),
+            coord(self.lat),
+            coord(self.lon),
+            out(config)
+        )
+    }
+}
+
+/// Searches for nodes inside the area by tag
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{Area, Config, Search};
+/// #[tokio::main]
+/// async fn main() {
+///   let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+///   let a = Area {
+///       name: "Cambridge".to_string(),
+///       admin_level: Some(8),
+///   };
+///
+///   let resp = a.search(&c).await.expect("failed query");
+/// }
+/// ```
+impl Search for Area {
+    /// Check that the area has a name
+    fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidQuery("the area needs a name".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Overpass QL sent by the search methods
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{Area, Config, Search};
+    ///
+    /// let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+    /// let a = Area { name: "Cambridge".to_string(), admin_level: None };
+    /// assert_eq!(
+    ///     a.build_query(&c),
+    ///     "[out:json][timeout:25];area[\"name\"=\"Cambridge\"]->.a;node[\"amenity\"=\"cafe\"](area.a);out center;"
+    /// );
+    /// ```
+    fn build_query(&self, config: &Config) -> String {
+        let admin_level = self
+            .admin_level
+            .map(|l| format!("[\"admin_level\"=\"{}\"]", l))
+            .unwrap_or_default();
+        format!(
+            "{}area[\"name\"=\"{}\"]{}->.a;{}{}(area.a);{}",
+            settings_for_area(config, f64::INFINITY),
+            ql_escape(&self.name),
+            admin_level,
+            config.element_type.selector(),
+            tag_selectors(config),
+            out(config)
+        )
+    }
+}
+
+impl Polygon {
+    /// Approximate area of the polygon in km², by the shoelace formula on
+    /// an equirectangular projection at its mean latitude
+    pub fn area_km2(&self) -> f64 {
+        if self.points.len() < 3 {
+            return 0.0;
+        }
+        let n = self.points.len() as f64;
+        let lat0 = (self.points.iter().map(|p| p.0).sum::<f64>() / n).to_radians();
+        let r = wgs84_earth_radius(lat0) / 1000.0;
+        let project =
+            |&(lat, lon): &(f64, f64)| (lon.to_radians() * r * lat0.cos(), lat.to_radians() * r);
+        let xy: Vec<(f64, f64)> = self.points.iter().map(project).collect();
+        let twice: f64 = xy
+            .iter()
+            .zip(xy.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum();
+        twice.abs() / 2.0
+    }
+}
+
+/// Searches for nodes inside the polygon by tag
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{Config, Polygon, Search};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+///     let p = Polygon {
+///         points: vec![(51.50, -0.13), (51.51, -0.13), (51.505, -0.12)],
+///     };
+///     let resp = p.search(&c).await.expect("failed query");
+/// }
+/// ```
+impl Search for Polygon {
+    /// Check that the polygon has at least three vertices, all of them
+    /// valid coordinates
+    fn validate(&self) -> Result<(), Error> {
+        if self.points.len() < 3 {
+            return Err(Error::InvalidQuery(format!(
+                "polygon needs at least 3 points, got {}",
+                self.points.len()
+            )));
+        }
+        for &(lat, lon) in &self.points {
+            if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                return Err(Error::InvalidQuery(format!(
+                    "invalid polygon point ({}, {})",
+                    lat, lon
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Overpass QL sent by the search methods
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{Config, Polygon, Search};
+    ///
+    /// let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+    /// let p = Polygon {
+    ///     points: vec![(51.5, -0.13), (51.51, -0.13), (51.505, -0.12)],
+    /// };
+    /// assert_eq!(
+    ///     p.build_query(&c),
+    ///     "[out:json][timeout:10];node[\"amenity\"=\"cafe\"]\
+    ///      (poly:\"51.5 -0.13 51.51 -0.13 51.505 -0.12\");out center;"
+    /// );
+    /// ```
+    fn build_query(&self, config: &Config) -> String {
+        let poly: Vec<String> = self
+            .points
+            .iter()
+            .map(|&(lat, lon)| format!("{} {}", coord(lat), coord(lon)))
+            .collect();
+        format!(
+            "{}{}{}(poly:\"{}\");{}",
+            settings_for_area(config, self.area_km2()),
+            config.element_type.selector(),
+            tag_selectors(config),
+            poly.join(" "),
+            out(config)
+        )
+    }
+}
+
+impl AreaElement {
+    /// Way or relation the area was built from, decoded from its id
+    pub fn source(&self) -> Option<(OsmType, u64)> {
+        if self.id > AREA_RELATION_OFFSET {
+            Some((OsmType::Relation, self.id - AREA_RELATION_OFFSET))
+        } else if self.id > AREA_WAY_OFFSET {
+            Some((OsmType::Way, self.id - AREA_WAY_OFFSET))
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.tags.get("name").map(String::as_str)
+    }
+
+    /// `admin_level` of an administrative boundary, 2 for a country
+    pub fn admin_level(&self) -> Option<u8> {
+        self.tags.get("admin_level")?.parse().ok()
+    }
+}
+
+impl AreasResponse {
+    /// The server's remark, if any
+    pub fn warning(&self) -> Option<&str> {
+        self.remark.as_deref()
+    }
+
+    /// Whether the server sent partial results, see
+    /// [`OverpassResponse::is_truncated`]
+    pub fn is_truncated(&self) -> bool {
+        is_truncated(self.remark.as_deref())
+    }
+
+    /// Administrative boundaries containing the point, from the country
+    /// down to the most local one
+    pub fn administrative(&self) -> Vec<&AreaElement> {
+        let mut areas: Vec<&AreaElement> = self
+            .elements
+            .iter()
+            .filter(|a| {
+                a.tags
+                    .get("boundary")
+                    .is_some_and(|b| b == "administrative")
+            })
+            .filter(|a| a.admin_level().is_some())
+            .collect();
+        areas.sort_by_key(|a| a.admin_level());
+        areas
+    }
+}
+
+impl IsIn {
+    /// Check that the point is within latitude and longitude range
+    pub fn validate(&self) -> Result<(), Error> {
+        if !((-90.0..=90.0).contains(&self.lat) && (-180.0..=180.0).contains(&self.lon)) {
+            return Err(Error::InvalidQuery(format!(
+                "point ({}, {}) out of range",
+                self.lat, self.lon
+            )));
+        }
+        Ok(())
+    }
+
+    /// Asynchronously look up the areas containing the point, keeping those
+    /// that match the config's tags when it has any. Areas are printed with
+    /// a plain `out`, so a config setting `output`, `quadtile` or `limit` is
+    /// rejected with [`Error::InvalidConfig`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{Config, IsIn};
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let c = Config::builder().tag("boundary", "administrative").build().unwrap();
+    ///     let p = IsIn { lat: 42.3554334, lon: -71.060511 };
+    ///     let resp = p.search(&c).await.expect("failed query");
+    ///     for a in resp.administrative() {
+    ///         println!("{:?} {:?}", a.admin_level(), a.name());
+    ///     }
+    /// }
+    /// ```
+    pub async fn search(&self, config: &Config) -> Result<AreasResponse, Error> {
+        self.validate()?;
+        if config.output != OutputDetail::default() || config.quadtile || config.limit.is_some() {
+            return Err(Error::InvalidConfig(
+                "is_in does not support output, quadtile or limit".to_string(),
+            ));
+        }
+        send_as(config, self.build_query(config)).await
+    }
+
+    /// Overpass QL sent by [`IsIn::search`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{Config, IsIn};
+    ///
+    /// let c = Config::builder().tag("boundary", "administrative").build().unwrap();
+    /// let p = IsIn { lat: 42.36, lon: -71.06 };
+    /// assert_eq!(
+    ///     p.build_query(&c),
+    ///     "[out:json][timeout:10];is_in(42.36,-71.06)->.a;area.a[\"boundary\"=\"administrative\"];out;"
+    /// );
+    /// ```
+    pub fn build_query(&self, config: &Config) -> String {
+        let tags = tag_selectors(config);
+        let select = if tags.is_empty() {
+            String::new()
+        } else {
+            format!("->.a;area.a{}", tags)
+        };
+        format!(
+            "{}is_in({},{}){};out;",
+            settings_for_area(config, 0.0),
+            coord(self.lat),
+            coord(self.lon),
+            select
+        )
+    }
+}
+
+/// Searches for elements near the base set by tag
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{AroundSet, BoundingBox, Config, ElementType, Filter, Search};
+/// #[tokio::main]
+/// async fn main() {
+///     let c = Config::builder().tag("amenity", "bench").build().unwrap();
+///     let benches = AroundSet {
+///         base_type: ElementType::Way,
+///         base: vec![Filter::Eq("highway".into(), "footway".into())],
+///         bbox: BoundingBox { xmin: -0.13, ymin: 51.50, xmax: -0.12, ymax: 51.51 },
+///         radius_m: 50.0,
+///     };
+///     let resp = benches.search(&c).await.expect("failed query");
+/// }
+/// ```
+impl Search for AroundSet {
+    /// Check that the box is valid, the base set has a selector and the
+    /// radius is positive
+    fn validate(&self) -> Result<(), Error> {
+        self.bbox.validate()?;
+        if self.base.is_empty() {
+            return Err(Error::InvalidQuery(
+                "the base set needs at least one filter".to_string(),
+            ));
+        }
+        if !(self.radius_m.is_finite() && self.radius_m > 0.0) {
+            return Err(Error::InvalidQuery(format!(
+                "radius must be positive, got {}",
+                self.radius_m
+            )));
+        }
+        Ok(())
+    }
+
+    /// Overpass QL sent by the search methods, storing the base set in
+    /// `.base`
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use osm_rs::overpass::{AroundSet, BoundingBox, Config, ElementType, Filter, Search};
+    ///
+    /// let c = Config::builder().tag("amenity", "bench").build().unwrap();
+    /// let a = AroundSet {
+    ///     base_type: ElementType::Way,
+    ///     base: vec![Filter::Eq("highway".into(), "footway".into())],
+    ///     bbox: BoundingBox { xmin: -0.2, ymin: 51.4, xmax: 0.1, ymax: 51.6 },
+    ///     radius_m: 50.0,
+    /// };
+    /// assert_eq!(
+    ///     a.build_query(&c),
+    ///     "[out:json][timeout:15];way[\"highway\"=\"footway\"](51.4,-0.2,51.6,0.1)->.base;\
+    ///      node[\"amenity\"=\"bench\"](around.base:50);out center;"
+    /// );
+    /// ```
+    fn build_query(&self, config: &Config) -> String {
+        let base: String = self.base.iter().map(Filter::to_ql).collect();
+        format!(
+            "{}{}{}({},{},{},{})->.base;{}{}(around.base:{});{}",
+            settings_for_area(config, self.bbox.area_km2()),
+            self.base_type.selector(),
+            base,
+            coord(self.bbox.ymin),
+            coord(self.bbox.xmin),
+            coord(self.bbox.ymax),
+            coord(self.bbox.xmax),
+            config.element_type.selector(),
+            tag_selectors(config),
+            coord(self.radius_m),
+            out(config)
+        )
+    }
+}
+
+/// Search each of `boxes` with at most `concurrency` requests in flight,
+/// pausing `config.delay_between` after each one. Results are returned in
+/// the order of `boxes`.
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{search_all, BoundingBox, Config};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+///     let boxes = vec![
+///         BoundingBox { xmin: -0.13, ymin: 51.50, xmax: -0.12, ymax: 51.51 },
+///         BoundingBox { xmin: -0.12, ymin: 51.50, xmax: -0.11, ymax: 51.51 },
+///     ];
+///     let results = search_all(&boxes, &c, 2).await;
+///     assert_eq!(results.len(), 2);
+/// }
+/// ```
+pub async fn search_all(
+    boxes: &[BoundingBox],
+    config: &Config,
+    concurrency: usize,
+) -> Vec<Result<OverpassResponse, Error>> {
+    let mut results: Vec<(usize, Result<OverpassResponse, Error>)> =
+        stream::iter(boxes.iter().enumerate())
+            .map(|(i, b)| async move {
+                let res = b.search(config).await;
+                if let Some(delay) = config.delay_between {
+                    tokio::time::sleep(delay).await;
+                }
+                (i, res)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Search `bbox` on each of the mirrors in `urls` in turn, using them in
+/// place of `config.url`, until one answers. Moves on to the next mirror
+/// when a connection fails or times out, or on a 5xx or 429 status; other
+/// errors, such as a rejected query, are returned right away. When every
+/// mirror fails the last error is returned.
+///
+/// # Example
+///
+/// ```rust
+/// use osm_rs::overpass::{search_failover, BoundingBox, Config};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mirrors = [
+///         "https://overpass-api.de/api/interpreter".to_string(),
+///         "https://overpass.kumi.systems/api/interpreter".to_string(),
+///     ];
+///     let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+///     let b = BoundingBox { xmin: -0.13, ymin: 51.50, xmax: -0.12, ymax: 51.51 };
+///     let resp = search_failover(&mirrors, &c, &b).await.unwrap();
+/// }
+/// ```
+pub async fn search_failover(
+    urls: &[String],
+    config: &Config,
+    bbox: &BoundingBox,
+) -> Result<OverpassResponse, Error> {
+    let mut last = Error::InvalidConfig("no Overpass URLs to try".to_string());
+    for url in urls {
+        match bbox.search(&config.with_url(url)).await {
+            Err(e) if is_retryable(&e) => last = e,
+            res => return res,
+        }
+    }
+    Err(last)
+}
+
+/// Whether another server may answer where this error came from, i.e. a
+/// connection failure, a timeout or a 5xx or 429 status
+fn is_retryable(e: &Error) -> bool {
+    match e {
+        Error::Http(e) => e.is_connect() || e.is_timeout(),
+        Error::Unavailable { .. } => true,
+        _ => false,
+    }
+}
+
+/// Run `fut` until it completes or `token` is cancelled, in which case `fut`
+/// is dropped along with any connection it holds
+async fn cancellable<T>(
+    token: CancellationToken,
+    fut: impl std::future::Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(Error::Cancelled),
+        res = fut => res,
+    }
+}
+
+/// Post a query to the Overpass interpreter
+async fn send(config: &Config, query: String) -> Result<OverpassResponse, Error> {
+    let cache = config.cache.as_deref();
+    let key = Cache::key(config, &query);
+    if let Some(resp) = cache.and_then(|c| c.get(&key)) {
+        return Ok(resp);
+    }
+    let etag = cache.and_then(|c| c.etag(&key));
+    let res = fetch(config, query, etag.as_deref()).await?;
+    if res.not_modified {
+        return cache.and_then(|c| c.refresh(&key)).ok_or_else(|| {
+            Error::Overpass("304 Not Modified without a cached response".to_string())
+        });
+    }
+    let etag = res.etag.clone();
+    let resp: OverpassResponse = parse_json(res)?;
+    if let Some(cache) = cache {
+        cache.insert_with_etag(key, resp.clone(), etag);
+    }
+    Ok(resp)
+}
+
+/// Post a query to the Overpass interpreter, parsing the response as `T`
+/// without caching
+async fn send_as<T: serde::de::DeserializeOwned>(
+    config: &Config,
+    query: String,
+) -> Result<T, Error> {
+    parse_json(fetch(config, query, None).await?)
+}
+
+/// Parse a JSON body, turning error pages into [`Error::Overpass`]
+fn parse_json<T: serde::de::DeserializeOwned>(res: Fetched) -> Result<T, Error> {
+    if res
+        .content_type
+        .as_ref()
+        .is_some_and(|t| !t.contains("json"))
+    {
+        return Err(Error::Overpass(error_message(&res.body)));
+    }
+    let text = http::decode(&res.body, res.content_type.as_deref());
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// Post a query to the Overpass interpreter and return the raw body,
+/// turning HTML error pages into [`Error::Overpass`]
+async fn send_text(config: &Config, query: String) -> Result<String, Error> {
+    let res = fetch(config, query, None).await?;
+    if res
+        .content_type
+        .as_ref()
+        .is_some_and(|t| t.contains("html"))
+    {
+        return Err(Error::Overpass(error_message(&res.body)));
+    }
+    Ok(http::decode(&res.body, res.content_type.as_deref()))
+}
+
+/// Response of [`fetch`]
+struct Fetched {
+    /// The server answered `304 Not Modified` to `If-None-Match`
+    not_modified: bool,
+    content_type: Option<String>,
+    etag: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Post a query, revalidating against `etag` when given and retrying
+/// according to `config.retry`, failing with [`Error::Unavailable`] on a 429
+/// or 5xx status and [`Error::Overpass`] on other error statuses
+async fn fetch(config: &Config, query: String, etag: Option<&str>) -> Result<Fetched, Error> {
+    config.validate()?;
+    match &config.retry {
+        Some(policy) => retry::with_retry(policy, || fetch_once(config, query.clone(), etag)).await,
+        None => fetch_once(config, query, etag).await,
+    }
+}
+
+/// Post a query once, see [`fetch`]
+async fn fetch_once(config: &Config, query: String, etag: Option<&str>) -> Result<Fetched, Error> {
+    if config.url.to_lowercase().contains("nominatim") {
+        return Err(Error::LikelyWrongEndpoint(config.url.clone()));
+    }
+    if let Some(limiter) = &config.limiter {
+        limiter.wait().await;
+    }
+    let client = match &config.client {
+        Some(client) => client.clone(),
+        None => http::client(
+            &config.url,
+            config.user_agent.as_deref(),
+            None,
+            config.max_redirects,
+            config.compression,
+        )?,
+    };
+    let mut req = match config.method {
+        HttpMethod::Post => client.post(&config.url).body(query),
+        HttpMethod::Get => client.get(&config.url).query(&[("data", query)]),
+    };
+    if let Some(etag) = etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    let res = req.send().await?;
+    if let Some(e) = http::unavailable(&res) {
+        return Err(e);
+    }
+    let status = res.status();
+    let header = |name: HeaderName| {
+        res.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let content_type = header(CONTENT_TYPE).map(|t| t.to_lowercase());
+    let etag = header(ETAG);
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched {
+            not_modified: true,
+            content_type,
+            etag,
+            body: vec![],
+        });
+    }
+    let body = read_body(res, config.max_response_bytes).await?;
+    if !status.is_success() {
+        return Err(Error::Overpass(format!(
+            "{}: {}",
+            status,
+            error_message(&body)
+        )));
+    }
+    Ok(Fetched {
+        not_modified: false,
+        content_type,
+        etag,
+        body,
+    })
+}
+
+/// Readable message from an Overpass HTML or plain text error page: the
+/// lines mentioning an error with markup stripped, or all text otherwise
+fn error_message(body: &[u8]) -> String {
+    let html = String::from_utf8_lossy(body);
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let errors: Vec<&str> = lines
+        .iter()
+        .copied()
+        .filter(|l| l.to_lowercase().contains("error"))
+        .collect();
+    if errors.is_empty() {
+        lines.join(" ")
+    } else {
+        errors.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::registry::{EndpointDefaults, EndpointRegistry};
+    use tokio;
+
+    const LONDON_CAFES: &str = include_str!("../fixtures/overpass_london_cafes.json");
+    const LONDON_NWR: &str = include_str!("../fixtures/overpass_london_nwr.json");
+    const LONDON_DIFF: &str = include_str!("../fixtures/overpass_london_diff.json");
+    const LONDON_MIXED: &str = include_str!("../fixtures/overpass_london_mixed.json");
+
+    #[tokio::test]
+    async fn test_bounding_box() {
+        let c: Config = Config {
+            url: "https://overpass-api.de/api/interpreter".to_string(),
+            timeout: 25,
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            ..Default::default()
+        };
+        let b: BoundingBox = BoundingBox {
+            xmin: -0.7690429687500001,
+            ymin: 51.305219521963295,
+            xmax: 0.5273437500000064,
+            ymax: 51.82219818336938,
+        };
+        let resp = b.search(&c).await.unwrap();
+        assert!(!resp.elements.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_around() {
+        let c: Config = Config {
+            url: "https://overpass-api.de/api/interpreter".to_string(),
+            timeout: 25,
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            ..Default::default()
+        };
+        let a = Around {
+            lat: 51.5072,
+            lon: -0.1276,
+            radius_m: 500.0,
+        };
+        let resp = a.search(&c).await.unwrap();
+        assert!(!resp.elements.is_empty());
+    }
+
+    #[test]
+    fn test_build_query() {
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+        let b = BoundingBox {
+            xmin: -0.2,
+            ymin: 51.4,
+            xmax: 0.1,
+            ymax: 51.6,
+        };
+        assert_eq!(
+            b.build_query(&c),
+            "[out:json][timeout:15];node[\"amenity\"=\"cafe\"](51.4,-0.2,51.6,0.1);out center;"
+        );
+
+        let a = Around {
+            lat: 51.5,
+            lon: -0.1,
+            radius_m: 500.0,
+        };
+        assert_eq!(
+            a.build_query(&c),
+            "[out:json][timeout:10];node[\"amenity\"=\"cafe\"](around:500,51.5,-0.1);out center;"
+        );
+    }
+
+    #[test]
+    fn test_nwr_query() {
+        let c = Config::builder()
+            .tag("amenity", "cafe")
+            .element_type(ElementType::All)
+            .build()
+            .unwrap();
+        let b = BoundingBox {
+            xmin: -0.2,
+            ymin: 51.4,
+            xmax: 0.1,
+            ymax: 51.6,
+        };
+        assert_eq!(
+            b.build_query(&c),
+            "[out:json][timeout:15];nwr[\"amenity\"=\"cafe\"](51.4,-0.2,51.6,0.1);out center;"
+        );
+    }
+
+    #[test]
+    fn test_way_center() {
+        let n: Node = serde_json::from_str(
+            r#"{"type": "way", "id": 7, "center": {"lat": 51.5, "lon": -0.1},
+                "tags": {"amenity": "cafe"}}"#,
+        )
+        .unwrap();
+        assert_eq!(n.osm_type, OsmType::Way);
+        assert_eq!((n.lat, n.lon), (51.5, -0.1));
+
+        assert!(serde_json::from_str::<Node>(r#"{"type": "way", "id": 7}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_nwr_search() {
+        let c = Config::builder()
+            .tag("amenity", "cafe")
+            .element_type(ElementType::All)
+            .build()
+            .unwrap();
+        let b = BoundingBox {
+            xmin: -0.1376,
+            ymin: 51.5072,
+            xmax: -0.1176,
+            ymax: 51.5172,
+        };
+        let resp = b.search(&c).await.unwrap();
+        assert!(resp.elements.iter().any(|n| n.osm_type == OsmType::Node));
+        assert!(resp.elements.iter().any(|n| n.osm_type != OsmType::Node));
+    }
+
+    #[tokio::test]
+    async fn test_bounding_box_offline() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            ..Default::default()
+        };
+        let b = BoundingBox {
+            xmin: -0.7690429687500001,
+            ymin: 51.305219521963295,
+            xmax: 0.5273437500000064,
+            ymax: 51.82219818336938,
+        };
+        let resp = b.search(&c).await.unwrap();
+        assert_eq!(resp.elements.len(), 2);
+        assert_eq!(resp.elements[0].tags["name"], "Fleet");
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].body, b.build_query(&c));
+        assert_eq!(
+            seen[0].body,
+            "[out:json][timeout:25];node[\"amenity\"=\"cafe\"]\
+             (51.30521952,-0.76904297,51.82219818,0.52734375);out center;"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_around_offline() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            ..Default::default()
+        };
+        let a = Around {
+            lat: 51.5072,
+            lon: -0.1276,
+            radius_m: 500.0,
+        };
+        assert_eq!(a.search(&c).await.unwrap().elements.len(), 2);
+        assert_eq!(seen.lock().unwrap()[0].body, a.build_query(&c));
+        assert!(a.build_query(&c).contains("(around:500,51.5072,-0.1276);"));
+    }
+
+    #[tokio::test]
+    async fn test_nwr_search_offline() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_NWR)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .element_type(ElementType::All)
+            .build()
+            .unwrap();
+        let b = BoundingBox {
+            xmin: -0.1376,
+            ymin: 51.5072,
+            xmax: -0.1176,
+            ymax: 51.5172,
+        };
+        let resp = b.search(&c).await.unwrap();
+        assert_eq!(resp.elements[0].osm_type, OsmType::Node);
+        assert_eq!(resp.elements[1].osm_type, OsmType::Way);
+        assert_eq!(resp.elements[1].lat, 51.512);
+        assert!(seen.lock().unwrap()[0]
+            .body
+            .contains("nwr[\"amenity\"=\"cafe\"]"));
+    }
+
+    #[tokio::test]
+    async fn test_diff_search_offline() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_DIFF)).await;
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            diff_dates: Some((
+                "2023-01-01T00:00:00Z".to_string(),
+                "2024-01-01T00:00:00Z".to_string(),
+            )),
+            ..Default::default()
+        };
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let resp = b.search(&c).await.unwrap();
+        assert_eq!(resp.elements[0].action, Some(Action::Create));
+        assert_eq!(resp.elements[1].action, Some(Action::Delete));
+        let created: Vec<u64> = resp.changes(Action::Create).map(|n| n.id).collect();
+        assert_eq!(created, vec![1]);
+        assert_eq!(resp.changes(Action::Modify).count(), 0);
+        assert!(seen.lock().unwrap()[0]
+            .body
+            .contains("[diff:\"2023-01-01T00:00:00Z\",\"2024-01-01T00:00:00Z\"]"));
+    }
+
+    #[tokio::test]
+    async fn test_search_adiff() {
+        let (server, seen) = MockServer::recording(MockResponse::json(include_str!(
+            "../fixtures/overpass_london_adiff.json"
+        )))
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .diff_dates("2023-01-01T00:00:00Z", "2024-01-01T00:00:00Z")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let resp = b.search_adiff(&c).await.unwrap();
+        assert!(seen.lock().unwrap()[0]
+            .body
+            .contains("[adiff:\"2023-01-01T00:00:00Z\",\"2024-01-01T00:00:00Z\"]"));
+
+        let actions: Vec<Action> = resp.elements.iter().map(|e| e.action).collect();
+        assert_eq!(
+            actions,
+            [
+                Action::Modify,
+                Action::Create,
+                Action::Delete,
+                Action::Modify
+            ]
+        );
+        let renamed = &resp.elements[0];
+        assert_eq!(renamed.old.as_ref().unwrap().tags()["name"], "Fleet");
+        assert_eq!(
+            renamed.new.as_ref().unwrap().tags()["name"],
+            "Fleet Street Coffee"
+        );
+        let created = &resp.elements[1];
+        assert!(created.old.is_none());
+        assert_eq!(created.new.as_ref().unwrap().id(), 3);
+        let deleted = &resp.elements[2];
+        assert_eq!(deleted.old.as_ref().unwrap().id(), 2);
+        assert!(deleted.new.is_none());
+        // Left the selection by losing the amenity=cafe tag
+        let left = &resp.elements[3];
+        assert_eq!(left.new.as_ref().unwrap().tags()["amenity"], "restaurant");
+
+        let no_dates = Config {
+            diff_dates: None,
+            ..c
+        };
+        assert!(matches!(
+            b.search_adiff(&no_dates).await,
+            Err(Error::InvalidConfig(_))
+        ));
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert!(serde_json::from_str::<AdiffElement>(
+            r#"{"type": "node", "id": 1, "action": "modify"}"#
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_csv_offline() {
+        let csv = "@id\tname\n1\tFleet\n2\t\n";
+        let (server, seen) =
+            MockServer::recording(MockResponse::new(200, csv).header("Content-Type", "text/csv"))
+                .await;
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            format: OutputFormat::Csv(vec!["::id".to_string(), "name".to_string()]),
+            ..Default::default()
+        };
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        assert_eq!(b.search_csv(&c).await.unwrap(), csv);
+        assert!(seen.lock().unwrap()[0]
+            .body
+            .starts_with("[out:csv(::id,\"name\")]"));
+
+        // Also available on the other search types, but only for CSV output
+        let a = Around {
+            lat: 51.5072,
+            lon: -0.1276,
+            radius_m: 500.0,
+        };
+        assert_eq!(a.search_csv(&c).await.unwrap(), csv);
+        let json = Config {
+            format: OutputFormat::Json,
+            ..c
+        };
+        assert!(matches!(
+            a.search_csv(&json).await,
+            Err(Error::InvalidConfig(_))
+        ));
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_csv_error_page() {
+        let html = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html><body>
+<p><strong style="color:#FF0000">Error</strong>: runtime error: open64: 0 Success /osm3s_osm_base Dispatcher_Client::request_read_and_idx::timeout. The server is probably too busy to handle your request. </p>
+</body></html>"#;
+        let server = MockServer::start(vec![
+            MockResponse::new(200, html).header("Content-Type", "text/html; charset=utf-8")
+        ])
+        .await;
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            format: OutputFormat::Csv(vec!["::id".to_string()]),
+            ..Default::default()
+        };
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        match b.search_csv(&c).await {
+            Err(Error::Overpass(m)) => assert!(m.starts_with("Error: runtime error: open64")),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_diff_settings() {
+        let c = Config {
+            diff_dates: Some((
+                "2023-01-01T00:00:00Z".to_string(),
+                "2023-06-01T00:00:00Z".to_string(),
+            )),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings_with_timeout(&c, None),
+            "[out:json][diff:\"2023-01-01T00:00:00Z\",\"2023-06-01T00:00:00Z\"];"
+        );
+        assert_eq!(
+            settings_with_timeout(&Config::default(), None),
+            "[out:json];"
+        );
+
+        let adiff = Config::builder()
+            .tag("amenity", "cafe")
+            .diff_dates("2023-01-01T00:00:00Z", "2023-06-01T00:00:00Z")
+            .augmented_diff(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            settings_with_timeout(&adiff, None),
+            "[out:json][adiff:\"2023-01-01T00:00:00Z\",\"2023-06-01T00:00:00Z\"];"
+        );
+
+        let quoted = Config {
+            diff_dates: Some(("2023\"];node;out;[x:\"".to_string(), "a\\b".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings_with_timeout(&quoted, None),
+            r#"[out:json][diff:"2023\"];node;out;[x:\"","a\\b"];"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diff_dates_validate() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_DIFF)).await;
+        let builder = || {
+            Config::builder()
+                .url(server.url("/api/interpreter"))
+                .tag("amenity", "cafe")
+        };
+        assert!(builder()
+            .diff_dates("2023-01-01T00:00:00Z", "2024-02-29T23:59:59Z")
+            .build()
+            .is_ok());
+        for bad in [
+            "2023-01-01",
+            "2023-01-01 00:00:00Z",
+            "2023-13-01T00:00:00Z",
+            "2023-01-01T24:00:00Z",
+            "2023-01-01T00:00:00+01:00",
+            "2023\"];node;out;",
+        ] {
+            assert!(matches!(
+                builder().diff_dates(bad, "2024-01-01T00:00:00Z").build(),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+
+        // Struct literals skip the builder and are checked before sending
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            diff_dates: Some(("yesterday".to_string(), "today".to_string())),
+            ..Default::default()
+        };
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        assert!(matches!(b.search(&c).await, Err(Error::InvalidConfig(_))));
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_config_serde() {
+        let c = Config::builder()
+            .url("https://overpass.kumi.systems/api/interpreter")
+            .timeout(60)
+            .filter(Filter::any_of(
+                "amenity",
+                vec!["cafe".into(), "restaurant".into()],
+            ))
+            .element_type(ElementType::All)
+            .output(OutputDetail::Tags)
+            .method(HttpMethod::Get)
+            .cache(Arc::new(Cache::new(Duration::from_secs(60))))
+            .build()
+            .unwrap()
+            .with_shared_limiter(Arc::new(RateLimiter::new(Duration::from_secs(1))));
+        let json = serde_json::to_string(&c).unwrap();
+        assert!(json.contains("\"element_type\":\"all\""));
+        assert!(json.contains("\"any_of\":[\"amenity\",[\"cafe\",\"restaurant\"]]"));
+        let back: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.url, c.url);
+        assert_eq!(back.timeout, 60);
+        assert_eq!(back.filters, c.filters);
+        assert_eq!(back.element_type, ElementType::All);
+        assert_eq!(back.output, OutputDetail::Tags);
+        assert_eq!(back.method, HttpMethod::Get);
+        assert!(back.cache.is_none() && back.limiter.is_none() && back.client.is_none());
+
+        let partial: Config = serde_json::from_str(
+            r#"{"key": "amenity", "val": "cafe", "format": {"csv": ["::id", "name"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(partial.url, Config::default().url);
+        assert_eq!(
+            partial.format,
+            OutputFormat::Csv(vec!["::id".to_string(), "name".to_string()])
+        );
+        assert!(partial.compression && partial.auto_timeout);
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+        assert_eq!(c.url, "https://overpass-api.de/api/interpreter");
+        assert_eq!(c.timeout, 25);
+        assert_eq!(c.key, "amenity");
+        assert_eq!(c.val, "cafe");
+
+        let c = Config::builder()
+            .url("https://overpass.kumi.systems/api/interpreter")
+            .timeout(60)
+            .tag("shop", "bakery")
+            .build()
+            .unwrap();
+        assert_eq!(c.url, "https://overpass.kumi.systems/api/interpreter");
+        assert_eq!(c.timeout, 60);
+
+        assert!(matches!(
+            Config::builder().url("not a url").tag("a", "b").build(),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            Config::builder()
+                .url("ftp://example.com")
+                .tag("a", "b")
+                .build(),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            Config::builder().build(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_max_response_bytes() {
+        let server = MockServer::start(vec![MockResponse::json(LONDON_CAFES)]).await;
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            max_response_bytes: Some(64),
+            ..Default::default()
+        };
+        assert!(matches!(
+            b.search(&c).await,
+            Err(Error::ResponseTooLarge(64))
+        ));
+
+        let c = Config {
+            max_response_bytes: Some(1 << 20),
+            ..c
+        };
+        let resp = b.search(&c).await.unwrap();
+        assert_eq!(resp.elements.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let cache = Arc::new(Cache::new(Duration::from_secs(60)));
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .cache(cache.clone())
+            .build()
+            .unwrap();
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        // The same query sent with GET is cached apart
+        let get = Config {
+            method: HttpMethod::Get,
+            ..c.clone()
+        };
+        assert_eq!(b.search(&get).await.unwrap().elements.len(), 2);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+
+        // Nothing listens on port 1, so sharing the entry would be wrong
+        let offline = c.with_url("http://127.0.0.1:1/api/interpreter");
+        assert!(b.search(&offline).await.is_err());
+
+        cache.clear();
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let cache = Cache::new(Duration::ZERO);
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        cache.insert("q".to_string(), resp);
+        assert!(cache.get("q").is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        let cache = Cache::new(Duration::from_secs(60)).with_capacity(2);
+        cache.insert("a".to_string(), resp.clone());
+        cache.insert("b".to_string(), resp.clone());
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), resp.clone());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+
+        // Expired entries with an ETag are kept until the max age only
+        let cache = Cache::new(Duration::ZERO).with_max_age(Duration::from_millis(50));
+        cache.insert_with_etag("q".to_string(), resp.clone(), Some("\"v1\"".to_string()));
+        assert!(cache.get("q").is_none());
+        assert_eq!(cache.etag("q").as_deref(), Some("\"v1\""));
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.etag("q"), None);
+        cache.insert("r".to_string(), resp);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.etag("q"), None);
+    }
+
+    #[tokio::test]
+    async fn test_cache_etag() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let server = MockServer::with_handler(move |req| {
+            let etag = req.header("if-none-match").map(str::to_string);
+            log.lock().unwrap().push(etag.clone());
+            match etag.as_deref() {
+                Some("\"v1\"") => MockResponse::new(304, ""),
+                _ => MockResponse::json(LONDON_CAFES).header("ETag", "\"v1\""),
+            }
+        })
+        .await;
+        // Entries expire at once, so every search revalidates
+        let cache = Arc::new(Cache::new(Duration::ZERO));
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .cache(cache.clone())
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+        let key = Cache::key(&c, &b.build_query(&c));
+        assert_eq!(cache.etag(&key).as_deref(), Some("\"v1\""));
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![None, Some("\"v1\"".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_method() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let b = BoundingBox {
+            xmin: -0.2,
+            ymin: 51.4,
+            xmax: 0.1,
+            ymax: 51.6,
+        };
+        let post = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let get = Config {
+            method: HttpMethod::Get,
+            ..post.clone()
+        };
+        b.search(&post).await.unwrap();
+        assert_eq!(b.search(&get).await.unwrap().elements.len(), 2);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].method, "POST");
+        assert_eq!(seen[0].body, b.build_query(&post));
+        assert_eq!(seen[1].method, "GET");
+        assert!(seen[1].body.is_empty());
+        let url = Url::parse(&format!("http://localhost{}", seen[1].target)).unwrap();
+        let data = url.query_pairs().find(|(k, _)| k == "data").unwrap().1;
+        assert_eq!(data, b.build_query(&get));
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        b.search(&c).await.unwrap();
+        b.search(&Config {
+            compression: false,
+            ..c
+        })
+        .await
+        .unwrap();
+
+        let seen = seen.lock().unwrap();
+        let accepts = |i: usize, enc: &str| {
+            seen[i]
+                .header("accept-encoding")
+                .is_some_and(|v| v.contains(enc))
+        };
+        assert_eq!(accepts(0, "gzip"), cfg!(feature = "gzip"));
+        assert_eq!(accepts(0, "deflate"), cfg!(feature = "deflate"));
+        assert!(!accepts(1, "gzip") && !accepts(1, "deflate"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_policy() {
+        let server = MockServer::with_handler(|req| {
+            if req.target == "/old" {
+                MockResponse::new(301, "").header("Location", "/api/interpreter")
+            } else {
+                MockResponse::json(LONDON_CAFES)
+            }
+        })
+        .await;
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let c = Config::builder()
+            .url(server.url("/old"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+
+        let one = Config {
+            max_redirects: Some(1),
+            ..c.clone()
+        };
+        assert_eq!(b.search(&one).await.unwrap().elements.len(), 2);
+
+        let none = Config {
+            max_redirects: Some(0),
+            ..c
+        };
+        match b.search(&none).await {
+            Err(Error::Overpass(m)) => assert!(m.starts_with("301")),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_defaults() {
+        let server = MockServer::with_handler(|req| {
+            assert_eq!(req.header("user-agent"), Some("registered/1.0"));
+            assert_eq!(req.header("x-api-key"), Some("secret"));
+            MockResponse::json(LONDON_CAFES)
+        })
+        .await;
+        let url = server.url("/api/interpreter");
+        let host = url.trim_start_matches("http://").split('/').next().unwrap();
+        EndpointRegistry::global().register(
+            host,
+            EndpointDefaults {
+                user_agent: Some("registered/1.0".to_string()),
+                headers: vec![("X-Api-Key".to_string(), "secret".to_string())],
+            },
+        );
+        let c = Config::builder()
+            .url(url.clone())
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let res = b.search(&c).await;
+        EndpointRegistry::global().unregister(host);
+        assert_eq!(res.unwrap().elements.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_user_agent() {
+        let server = MockServer::with_handler(|req| {
+            assert_eq!(req.header("user-agent"), Some("my-app/1.0"));
+            MockResponse::json(LONDON_CAFES)
+        })
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .user_agent("my-app/1.0")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+    }
+
+    #[test]
+    fn test_config_with_overrides() {
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+
+        let r = c.with_val("restaurant");
+        assert_eq!(r.val, "restaurant");
+        assert_eq!(
+            (r.url, r.timeout, r.key),
+            (c.url.clone(), c.timeout, c.key.clone())
+        );
+
+        let s = c.with_key("shop").with_val("bakery").with_timeout(60);
+        assert_eq!(
+            (s.key.as_str(), s.val.as_str(), s.timeout),
+            ("shop", "bakery", 60)
+        );
+        assert_eq!(s.url, c.url);
+
+        let m = c.with_url("https://overpass.kumi.systems/api/interpreter");
+        assert_eq!(m.url, "https://overpass.kumi.systems/api/interpreter");
+        assert_eq!((m.key, m.val), (c.key, c.val));
+    }
+
+    #[test]
+    fn test_haversine_km() {
+        assert_eq!(haversine_km(51.5, -0.12, 51.5, -0.12), 0.0);
+        // London to Paris is roughly 344 km
+        let d = haversine_km(51.5072, -0.1276, 48.8566, 2.3522);
+        assert!((d - 344.0).abs() < 2.0, "{}", d);
+        // One degree along the equator is roughly 111.3 km
+        let d = haversine_km(0.0, 0.0, 0.0, 1.0);
+        assert!((d - 111.3).abs() < 0.1, "{}", d);
+    }
+
+    #[test]
+    fn test_wgs84_earth_radius_fast() {
+        for i in -900..=900 {
+            let lat = i as f64 * 0.1;
+            let exact = wgs84_earth_radius(lat.to_radians());
+            let fast = wgs84_earth_radius_fast(lat);
+            assert!((fast - exact).abs() < 2.0, "{}: {} vs {}", lat, fast, exact);
+        }
+        assert_eq!(wgs84_earth_radius_fast(0.0), WGS84A);
+    }
+
+    #[tokio::test]
+    async fn test_search_to_geojson_file() {
+        let server = MockServer::start(vec![MockResponse::json(LONDON_CAFES)]).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let path = std::env::temp_dir().join(format!("osm-rs-{}.geojson", std::process::id()));
+
+        b.search_to_geojson_file(&c, &path).await.unwrap();
+        let geojson: serde_json::Value =
+            serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0]["id"], "node/1");
+        assert_eq!(features[0]["properties"]["name"], "Fleet");
+        assert_eq!(
+            features[0]["geometry"]["coordinates"],
+            serde_json::json!([-0.1276, 51.5072])
+        );
+    }
+
+    #[test]
+    fn test_osm_url() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        assert_eq!(resp.elements[0].osm_ref(), "node/1");
+        assert_eq!(
+            resp.elements[0].osm_url(),
+            "https://www.openstreetmap.org/node/1"
+        );
+
+        let way = Node {
+            osm_type: OsmType::Way,
+            id: 42,
+            ..resp.elements[0].clone()
+        };
+        assert_eq!(way.osm_url(), "https://www.openstreetmap.org/way/42");
+        let relation = Node {
+            osm_type: OsmType::Relation,
+            ..way
+        };
+        assert_eq!(relation.osm_ref(), "relation/42");
+    }
+
+    #[test]
+    fn test_missing_tag() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        let ids: Vec<u64> = resp.missing_tag("name").iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![2]);
+        assert!(resp.missing_tag("amenity").is_empty());
+        assert_eq!(resp.missing_tag("opening_hours").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_is_in() {
+        let (server, seen) = MockServer::recording(MockResponse::json(include_str!(
+            "../fixtures/overpass_boston_is_in.json"
+        )))
+        .await;
+        let c = Config {
+            url: server.url("/api/interpreter"),
+            ..Default::default()
+        };
+        let p = IsIn {
+            lat: 42.3554334,
+            lon: -71.060511,
+        };
+        let resp = p.search(&c).await.unwrap();
+        assert_eq!(resp.elements.len(), 4);
+        assert_eq!(
+            seen.lock().unwrap()[0].body,
+            "[out:json][timeout:10];is_in(42.3554334,-71.060511);out;"
+        );
+
+        let names: Vec<&str> = resp
+            .administrative()
+            .iter()
+            .filter_map(|a| a.name())
+            .collect();
+        assert_eq!(names, ["United States", "Massachusetts", "Boston"]);
+        assert_eq!(
+            resp.elements[2].source(),
+            Some((OsmType::Relation, 2315704))
+        );
+        assert_eq!(resp.elements[3].source(), Some((OsmType::Way, 12345)));
+        assert_eq!(resp.elements[3].admin_level(), None);
+
+        assert!(matches!(
+            IsIn {
+                lat: 91.0,
+                lon: 0.0
+            }
+            .search(&c)
+            .await,
+            Err(Error::InvalidQuery(_))
+        ));
+        for c in [
+            Config {
+                output: OutputDetail::Tags,
+                ..c.clone()
+            },
+            Config {
+                quadtile: true,
+                ..c.clone()
+            },
+            Config {
+                limit: Some(1),
+                ..c.clone()
+            },
+        ] {
+            assert!(matches!(p.search(&c).await, Err(Error::InvalidConfig(_))));
+        }
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let cafes: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        let nwr: OverpassResponse = serde_json::from_str(LONDON_NWR).unwrap();
+        let mut dup = cafes.clone();
+        dup.elements.push(cafes.elements[0].clone());
+        dup.osm3s.timestamp_osm_base = "later".to_string();
+
+        let merged = dup.merge([cafes.clone(), nwr]);
+        let ids: Vec<(OsmType, u64)> = merged.elements.iter().map(|n| (n.osm_type, n.id)).collect();
+        // Node 1 of the nwr response is a duplicate, way 10 is not
+        assert_eq!(
+            ids,
+            [(OsmType::Node, 1), (OsmType::Node, 2), (OsmType::Way, 10)]
+        );
+        assert_eq!(merged.osm3s.timestamp_osm_base, "later");
+
+        let truncated = OverpassResponse {
+            remark: Some("runtime error: Query timed out".to_string()),
+            ..cafes.clone()
+        };
+        assert!(cafes.clone().merge([truncated]).is_truncated());
+        assert!(cafes.clone().merge([]).remark.is_none());
+    }
+
+    #[test]
+    fn test_remark() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        assert_eq!(resp.warning(), None);
+        assert!(!resp.is_truncated());
+        assert!(!serde_json::to_string(&resp).unwrap().contains("remark"));
+
+        let timed_out = with_remark(
+            LONDON_CAFES,
+            "runtime error: Query timed out in \"query\" at line 1 after 26 seconds.",
+        );
+        let resp: OverpassResponse = serde_json::from_value(timed_out).unwrap();
+        assert!(resp.is_truncated());
+        assert!(resp
+            .warning()
+            .unwrap()
+            .starts_with("runtime error: Query timed out"));
+        assert_eq!(resp.elements.len(), 2);
+
+        let remark = OverpassResponse {
+            remark: Some("runtime remark: Timeout is 25 and maxsize is 536870912.".to_string()),
+            ..resp
+        };
+        assert!(!remark.is_truncated());
+        assert!(remark.warning().is_some());
+    }
+
+    #[test]
+    fn test_remark_on_other_responses() {
+        let timed_out = "runtime error: Query timed out";
+        let tags: TagsResponse =
+            serde_json::from_value(with_remark(LONDON_CAFES, timed_out)).unwrap();
+        assert!(tags.is_truncated());
+        assert_eq!(tags.warning(), Some(timed_out));
+        let elements: ElementsResponse =
+            serde_json::from_value(with_remark(LONDON_NWR, timed_out)).unwrap();
+        assert!(elements.is_truncated());
+        let areas: AreasResponse = serde_json::from_value(with_remark(
+            include_str!("../fixtures/overpass_boston_is_in.json"),
+            timed_out,
+        ))
+        .unwrap();
+        assert!(areas.is_truncated());
+
+        let elements: ElementsResponse = serde_json::from_str(LONDON_NWR).unwrap();
+        assert_eq!(elements.warning(), None);
+        assert!(!elements.is_truncated());
+    }
+
+    /// `fixture` with the server's `remark` set to `remark`
+    fn with_remark(fixture: &str, remark: &str) -> serde_json::Value {
+        let mut v: serde_json::Value = serde_json::from_str(fixture).unwrap();
+        v["remark"] = remark.into();
+        v
+    }
+
+    #[test]
+    fn test_filter_by_tag() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        let ids: Vec<u64> = resp
+            .filter_by_tag("amenity", "cafe")
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(resp.filter_by_tag("amenity", "pub").count(), 0);
+        assert_eq!(resp.filter_by_tag("wheelchair", "yes").count(), 0);
+
+        let named: Vec<u64> = resp
+            .filter_by(|n| n.name().is_some())
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(named, vec![1]);
+        let north = resp
+            .filter_by(|n| n.lat > 51.5)
+            .filter(|n| n.tag("amenity") == Some("cafe"))
+            .count();
+        assert_eq!(north, resp.elements.iter().filter(|n| n.lat > 51.5).count());
+    }
+
+    #[test]
+    fn test_coverage_box() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        let b = resp.coverage_box(1.0).unwrap();
+        for n in &resp.elements {
+            assert!(b.xmin < n.lon && n.lon < b.xmax);
+            assert!(b.ymin < n.lat && n.lat < b.ymax);
+        }
+        // The margin is at least 1 km beyond the outermost nodes
+        assert!(haversine_km(51.5072, -0.1276, b.ymin, -0.1276) >= 0.99);
+        assert!(haversine_km(51.5101, -0.1340, 51.5101, b.xmin) >= 0.99);
+        assert!(haversine_km(51.5101, -0.1276, 51.5101, b.xmax) >= 0.99);
+        assert!(haversine_km(51.5101, -0.1340, b.ymax, -0.1340) >= 0.99);
+
+        let empty = OverpassResponse {
+            elements: vec![],
+            ..resp
+        };
+        assert!(empty.coverage_box(1.0).is_none());
+    }
+
+    #[test]
+    fn test_nearest() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        assert_eq!(resp.nearest(51.5072, -0.1276).unwrap().id, 1);
+        assert_eq!(resp.nearest(51.52, -0.14).unwrap().id, 2);
+
+        let empty = OverpassResponse {
+            elements: vec![],
+            ..resp
+        };
+        assert!(empty.nearest(51.5, -0.12).is_none());
+    }
+
+    #[test]
+    fn test_tags_order() {
+        let n: Node = serde_json::from_str(
+            r#"{"type": "node", "id": 1, "lat": 51.5, "lon": -0.1, "tags": {
+                "name": "Fleet", "amenity": "cafe", "wheelchair": "yes",
+                "cuisine": "coffee_shop", "addr:street": "Fleet Street", "opening_hours": "24/7"
+            }}"#,
+        )
+        .unwrap();
+        let keys: Vec<&str> = n.tags.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            [
+                "name",
+                "amenity",
+                "wheelchair",
+                "cuisine",
+                "addr:street",
+                "opening_hours"
+            ]
+        );
+        assert!(serde_json::to_string(&n)
+            .unwrap()
+            .contains(r#""tags":{"name":"Fleet","amenity":"cafe","wheelchair":"yes","#));
+    }
+
+    #[test]
+    fn test_auto_timeout() {
+        let c = Config::builder()
+            .tag("amenity", "cafe")
+            .timeout(180)
+            .build()
+            .unwrap();
+        let tiny = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let huge = BoundingBox {
+            xmin: -10.0,
+            ymin: 40.0,
+            xmax: 10.0,
+            ymax: 55.0,
+        };
+        assert!(tiny.build_query(&c).starts_with("[out:json][timeout:10];"));
+        assert!(huge.build_query(&c).starts_with("[out:json][timeout:180];"));
+
+        let fixed = Config {
+            auto_timeout: false,
+            ..c
+        };
+        assert!(tiny
+            .build_query(&fixed)
+            .starts_with("[out:json][timeout:180];"));
+
+        assert_eq!(scaled_timeout(0.0, 25), MIN_TIMEOUT);
+        assert_eq!(scaled_timeout(1000.0, 25), 20);
+        assert_eq!(scaled_timeout(1e6, 25), 25);
+        assert_eq!(scaled_timeout(1000.0, 5), 5);
+        assert_eq!(scaled_timeout(f64::NAN, 25), 25);
+    }
+
+    #[test]
+    fn test_coordinate_geodesy() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+        let london = Coordinate {
+            lat: 51.5072,
+            lon: -0.1276,
+        };
+        let north = Coordinate {
+            lat: 52.5072,
+            ..london
+        };
+        assert!(close(london.bearing_to(&north), 0.0));
+        assert!(close(north.bearing_to(&london), 180.0));
+        let origin = Coordinate { lat: 0.0, lon: 0.0 };
+        assert!(close(
+            origin.bearing_to(&Coordinate { lat: 0.0, lon: 1.0 }),
+            90.0
+        ));
+        assert!(close(
+            origin.bearing_to(&Coordinate {
+                lat: 0.0,
+                lon: -1.0
+            }),
+            270.0
+        ));
+
+        let mid = london.midpoint(&north);
+        assert!(close(mid.lon, london.lon));
+        assert!(london.lat < mid.lat && mid.lat < north.lat);
+        // haversine_km uses the radius at each pair's mean latitude
+        assert!((mid.distance_km(&london) - mid.distance_km(&north)).abs() < 0.01);
+
+        let paris = Coordinate {
+            lat: 48.8566,
+            lon: 2.3522,
+        };
+        let mid = london.midpoint(&paris);
+        let half = london.distance_km(&paris) / 2.0;
+        assert!((mid.distance_km(&london) - half).abs() < 0.01);
+        assert!((mid.distance_km(&paris) - half).abs() < 0.01);
+    }
+
+    #[cfg(feature = "opening-hours")]
+    #[test]
+    fn test_is_open_at() {
+        let node = |hours: Option<&str>| {
+            let mut n: Node =
+                serde_json::from_str(r#"{"type": "node", "id": 1, "lat": 51.5, "lon": -0.1}"#)
+                    .unwrap();
+            if let Some(h) = hours {
+                n.tags.insert("opening_hours".to_string(), h.to_string());
+            }
+            n
+        };
+        // 2024-04-20 is a Saturday
+        let at = |d: u32, h: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 4, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let cafe = node(Some("Mo-Fr 08:00-18:00; Sa 10:00-14:00"));
+        assert_eq!(cafe.is_open_at(at(19, 9)), Some(true));
+        assert_eq!(cafe.is_open_at(at(19, 19)), Some(false));
+        assert_eq!(cafe.is_open_at(at(20, 9)), Some(false));
+        assert_eq!(cafe.is_open_at(at(20, 11)), Some(true));
+        assert_eq!(cafe.is_open_at(at(21, 11)), Some(false));
+
+        assert_eq!(node(Some("24/7")).is_open_at(at(21, 3)), Some(true));
+        assert_eq!(
+            node(Some("Mo-Fr 08:00-18:00 unknown")).is_open_at(at(19, 9)),
+            None
+        );
+        assert_eq!(
+            node(Some("whenever we feel like it")).is_open_at(at(19, 9)),
+            None
+        );
+        assert_eq!(node(None).is_open_at(at(19, 9)), None);
+    }
+
+    #[test]
+    fn test_tag_accessors() {
+        let n: Node = serde_json::from_str(
+            r#"{"type": "node", "id": 1, "lat": 51.5, "lon": -0.1, "tags": {
+                "name": "Fleet", "amenity": "cafe", "cuisine": "coffee_shop",
+                "contact:website": "https://fleet.example", "phone": "+44 20 7946 0000",
+                "website:menu": "https://fleet.example/menu"
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(n.tag("cuisine"), Some("coffee_shop"));
+        assert_eq!(n.tag("Name"), None);
+        assert_eq!(n.name(), Some("Fleet"));
+        assert_eq!(n.amenity(), Some("cafe"));
+        assert_eq!(n.shop(), None);
+        assert_eq!(n.website(), Some("https://fleet.example"));
+        assert_eq!(n.phone(), Some("+44 20 7946 0000"));
+        assert_eq!(n.opening_hours(), None);
+    }
+
+    #[test]
+    fn test_distance_rings() {
+        // 0.01 degrees of longitude on the equator is about 1.11 km
+        let resp = OverpassResponse {
+            elements: serde_json::from_str(
+                r#"[
+                    {"id": 1, "lat": 0.0, "lon": 0.005},
+                    {"id": 2, "lat": 0.0, "lon": 0.015},
+                    {"id": 3, "lat": 0.0, "lon": -0.0125},
+                    {"id": 4, "lat": 0.0, "lon": 0.0},
+                    {"id": 5, "lat": 0.0, "lon": 0.05}
+                ]"#,
+            )
+            .unwrap(),
+            ..serde_json::from_str(LONDON_CAFES).unwrap()
+        };
+        let ids: Vec<Vec<u64>> = resp
+            .distance_rings(0.0, 0.0, 1.0, 3)
+            .iter()
+            .map(|ring| ring.iter().map(|n| n.id).collect())
+            .collect();
+        assert_eq!(ids, vec![vec![1, 4], vec![2, 3], vec![]]);
+        assert!(resp
+            .distance_rings(0.0, 0.0, 0.0, 2)
+            .iter()
+            .all(Vec::is_empty));
+    }
 
     #[tokio::test]
-    async fn test_bounding_box() {
+    async fn test_wrong_endpoint() {
+        let c = Config::builder()
+            .url("https://nominatim.openstreetmap.org/search")
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        assert!(matches!(
+            b.search(&c).await,
+            Err(Error::LikelyWrongEndpoint(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_error_page() {
+        let html = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html><head><title>OSM3S Response</title></head>
+<body>
+<p>The data included in this document is from www.openstreetmap.org.</p>
+<p><strong style="color:#FF0000">Error</strong>: runtime error: Query timed out in "query" at line 1 after 26 seconds. </p>
+</body></html>"#;
+        let server = MockServer::start(vec![
+            MockResponse::new(200, html).header("Content-Type", "text/html; charset=utf-8"),
+            MockResponse::new(429, "rate_limited").header("Retry-After", "30"),
+        ])
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+
+        match b.search(&c).await {
+            Err(Error::Overpass(m)) => {
+                assert_eq!(m, "Error: runtime error: Query timed out in \"query\" at line 1 after 26 seconds.")
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        match b.search(&c).await {
+            Err(Error::Unavailable {
+                status: 429,
+                retry_after,
+            }) => assert_eq!(retry_after, Some(Duration::from_secs(30))),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_with_retry() {
+        let server = MockServer::start(vec![
+            MockResponse::new(429, "rate_limited").header("Retry-After", "0"),
+            MockResponse::new(503, "busy"),
+            MockResponse::json(LONDON_CAFES),
+            MockResponse::new(503, "busy"),
+            MockResponse::new(503, "busy"),
+        ])
+        .await;
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .retry(policy)
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+
+        assert_eq!(b.search(&c).await.unwrap().elements.len(), 2);
+        // Give up after two attempts, both answered with a 503
+        let c = Config {
+            retry: Some(RetryPolicy {
+                max_attempts: 2,
+                ..c.retry.clone().unwrap()
+            }),
+            ..c
+        };
+        assert!(matches!(
+            b.search(&c).await,
+            Err(Error::Unavailable { status: 503, .. })
+        ));
+    }
+
+    #[test]
+    fn test_plan_tiling() {
+        // Roughly 69 km x 58 km around London
+        let b = BoundingBox {
+            xmin: -0.6,
+            ymin: 51.25,
+            xmax: 0.4,
+            ymax: 51.77,
+        };
+        assert_eq!(b.plan_tiling(10_000, 1.0), (1, 1));
+        let (rows, cols) = b.plan_tiling(10_000, 10.0);
+        assert!(rows * cols >= 4);
+        let (dense_rows, dense_cols) = b.plan_tiling(10_000, 100.0);
+        assert!(dense_rows * dense_cols > rows * cols);
+        assert!(dense_rows * dense_cols >= 40);
+        assert_eq!(b.plan_tiling(0, 100.0), (1, 1));
+    }
+
+    #[test]
+    fn test_tiles() {
+        let b = BoundingBox {
+            xmin: 0.0,
+            ymin: 10.0,
+            xmax: 2.5,
+            ymax: 11.0,
+        };
+        let tiles = b.tiles(1.0);
+        assert_eq!(tiles.len(), 3);
+        assert_eq!((tiles[0].xmin, tiles[0].ymin), (0.0, 10.0));
+        assert_eq!((tiles[2].xmax, tiles[2].ymax), (2.5, 11.0));
+        assert!(tiles.iter().all(|t| t.xmax - t.xmin <= 1.0));
+        assert_eq!(b.tiles(10.0).len(), 1);
+        assert_eq!(b.tiles(0.0).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_tiled_stream() {
+        let server = MockServer::start(vec![MockResponse::json(LONDON_CAFES)]).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox {
+            xmin: -0.2,
+            ymin: 51.4,
+            xmax: 0.1,
+            ymax: 51.6,
+        };
+
+        let chunks: Vec<_> = b.search_tiled_stream(&c, 0.1).collect().await;
+        assert_eq!(chunks.len(), 6);
+        assert!(chunks
+            .iter()
+            .all(|r| r.as_ref().unwrap().elements.len() == 2));
+    }
+
+    #[tokio::test]
+    async fn test_search_tiled_remark() {
+        let server = MockServer::with_handler(|req| {
+            if req.body.contains("(51.5,0,") {
+                MockResponse::json(
+                    &with_remark(LONDON_CAFES, "runtime error: Query timed out").to_string(),
+                )
+            } else {
+                MockResponse::json(LONDON_CAFES)
+            }
+        })
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox {
+            xmin: -0.2,
+            ymin: 51.4,
+            xmax: 0.1,
+            ymax: 51.6,
+        };
+        let (resp, failed) = b.search_tiled(&c, 0.1, 1).await;
+        assert!(failed.is_empty());
+        assert!(resp.is_truncated());
+    }
+
+    #[tokio::test]
+    async fn test_search_tiled() {
+        let server = MockServer::with_handler(|req| {
+            if req.body.contains("(51.4,-0.2,") {
+                MockResponse::new(504, "Gateway Timeout")
+            } else {
+                MockResponse::json(LONDON_CAFES)
+            }
+        })
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox {
+            xmin: -0.2,
+            ymin: 51.4,
+            xmax: 0.1,
+            ymax: 51.6,
+        };
+
+        let (resp, failed) = b.search_tiled(&c, 0.1, 3).await;
+        // Every tile returns the same two nodes, which are merged once
+        assert_eq!(resp.elements.len(), 2);
+        assert_eq!(resp.generator, "Overpass API 0.7.62");
+        assert_eq!(failed.len(), 1);
+        let (tile, err) = &failed[0];
+        assert_eq!((tile.xmin, tile.ymin), (-0.2, 51.4));
+        assert!(matches!(err, Error::Unavailable { status: 504, .. }));
+
+        let c = Config {
+            url: "http://127.0.0.1:1/api/interpreter".to_string(),
+            ..c
+        };
+        let (resp, failed) = b.search_tiled(&c, 0.1, 3).await;
+        assert!(resp.elements.is_empty());
+        assert_eq!(failed.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_search_failover() {
+        let status = |code: u16| async move {
+            MockServer::start(vec![MockResponse::new(code, "<p>unavailable</p>")]).await
+        };
+        let (busy, down, bad) = (status(429).await, status(503).await, status(400).await);
+        let (ok, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let url = |s: &MockServer| s.url("/api/interpreter");
+        let refused = "http://127.0.0.1:1/api/interpreter".to_string();
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+
+        let mirrors = [refused.clone(), url(&busy), url(&down), url(&ok)];
+        let resp = search_failover(&mirrors, &c, &b).await.unwrap();
+        assert_eq!(resp.elements.len(), 2);
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        // A rejected query is not retried elsewhere
+        let mirrors = [url(&bad), url(&ok)];
+        match search_failover(&mirrors, &c, &b).await {
+            Err(Error::Overpass(m)) => assert!(m.starts_with("400")),
+            other => panic!("expected a 400, got {:?}", other.map(|r| r.elements.len())),
+        }
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        match search_failover(&[refused, url(&down)], &c, &b).await {
+            Err(Error::Unavailable { status: 503, .. }) => {}
+            other => panic!("expected a 503, got {:?}", other.map(|r| r.elements.len())),
+        }
+        assert!(matches!(
+            search_failover(&[], &c, &b).await,
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_elements() {
+        let resp: ElementsResponse = serde_json::from_str(LONDON_MIXED).unwrap();
+        assert_eq!(resp.elements.len(), 3);
+        match &resp.elements[0] {
+            Element::Node { id, lat, tags, .. } => {
+                assert_eq!((*id, *lat), (1, 51.5072));
+                assert_eq!(tags["name"], "Fleet");
+            }
+            e => panic!("expected a node, got {:?}", e),
+        }
+        match &resp.elements[1] {
+            Element::Way { id, nodes, .. } => {
+                assert_eq!(*id, 10);
+                assert_eq!(nodes, &[100, 101, 102, 100]);
+            }
+            e => panic!("expected a way, got {:?}", e),
+        }
+        match &resp.elements[2] {
+            Element::Relation { members, tags, .. } => {
+                assert_eq!(members[0].osm_type, OsmType::Way);
+                assert_eq!((members[0].id, members[0].role.as_str()), (10, "outer"));
+                // The `type` tag is a tag, not the element type
+                assert_eq!(tags["type"], "multipolygon");
+            }
+            e => panic!("expected a relation, got {:?}", e),
+        }
+
+        let types: Vec<OsmType> = resp.elements.iter().map(Element::osm_type).collect();
+        assert_eq!(types, [OsmType::Node, OsmType::Way, OsmType::Relation]);
+        assert_eq!(
+            resp.elements[2].location(),
+            Some(Coordinate {
+                lat: 51.509,
+                lon: -0.13
+            })
+        );
+        assert!(resp.elements.iter().all(|e| e.tags()["amenity"] == "cafe"));
+
+        let json = serde_json::to_string(&resp.elements).unwrap();
+        let back: Vec<Element> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, resp.elements);
+    }
+
+    #[tokio::test]
+    async fn test_search_elements() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_MIXED)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .element_type(ElementType::All)
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let resp = b.search_elements(&c).await.unwrap();
+        assert_eq!(resp.elements[1].id(), 10);
+        assert!(seen.lock().unwrap()[0]
+            .body
+            .contains("nwr[\"amenity\"=\"cafe\"]"));
+    }
+
+    #[tokio::test]
+    async fn test_polygon() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let p = Polygon {
+            points: vec![(51.5, -0.14), (51.52, -0.14), (51.52, -0.12), (51.5, -0.12)],
+        };
+        assert_eq!(p.search(&c).await.unwrap().elements.len(), 2);
+        assert!(seen.lock().unwrap()[0]
+            .body
+            .contains("(poly:\"51.5 -0.14 51.52 -0.14 51.52 -0.12 51.5 -0.12\");"));
+
+        // About 2.22 km tall and 1.39 km wide at this latitude
+        assert!((p.area_km2() - 3.08).abs() < 0.05);
+
+        let line = Polygon {
+            points: vec![(51.5, -0.14), (51.52, -0.14)],
+        };
+        assert!(matches!(line.search(&c).await, Err(Error::InvalidQuery(_))));
+        let bad = Polygon {
+            points: vec![(51.5, -0.14), (91.0, -0.14), (51.52, -0.12)],
+        };
+        assert!(matches!(bad.validate(), Err(Error::InvalidQuery(_))));
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_with_cancel() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let c = Config {
+            url: format!("http://{}/api/interpreter", listener.local_addr().unwrap()),
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            ..Default::default()
+        };
+        let a = Around {
+            lat: 51.5,
+            lon: -0.1,
+            radius_m: 500.0,
+        };
+
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+        let res = tokio::time::timeout(Duration::from_secs(5), a.search_with_cancel(&c, token))
+            .await
+            .expect("cancellation did not interrupt the request");
+        assert!(matches!(res, Err(Error::Cancelled)));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let b = BoundingBox {
+            xmin: -0.2,
+            ymin: 51.4,
+            xmax: 0.1,
+            ymax: 51.6,
+        };
+        assert!(matches!(
+            b.search_with_cancel(&c, token).await,
+            Err(Error::Cancelled)
+        ));
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn test_shared_limiter() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap()
+            .with_shared_limiter(Arc::new(RateLimiter::new(Duration::from_millis(50))));
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+
+        let start = tokio::time::Instant::now();
+        let (c1, c2) = (c.clone(), c.clone());
+        let (r1, r2, r3) = tokio::join!(b.search(&c), b.search(&c1), b.search(&c2));
+        assert!(r1.is_ok() && r2.is_ok() && r3.is_ok());
+        assert_eq!(seen.lock().unwrap().len(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_search_value() {
+        let body = r#"{"version": 0.6, "generator": "Overpass API", "elements": [
+            {"type": "way", "id": 10,
+             "bounds": {"minlat": 51.51, "minlon": -0.13, "maxlat": 51.52, "maxlon": -0.12},
+             "geometry": [{"lat": 51.51, "lon": -0.13}, {"lat": 51.52, "lon": -0.12}]}
+        ]}"#;
+        let server = MockServer::start(vec![MockResponse::json(body)]).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("highway", "primary")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let v = b.search_value(&c).await.unwrap();
+        let way = &v["elements"][0];
+        assert_eq!(way["bounds"]["maxlat"], 51.52);
+        assert_eq!(way["geometry"][1]["lon"], -0.12);
+
+        let a = Around {
+            lat: 51.5072,
+            lon: -0.1276,
+            radius_m: 100.0,
+        };
+        assert_eq!(a.search_value(&c).await.unwrap(), v);
+        // The typed search can't place a way without a center
+        assert!(b.search(&c).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_area() {
+        let server = MockServer::with_handler(|req| {
+            assert!(req
+                .body
+                .contains(r#"area["name"="Cambridge"]["admin_level"="8"]->.a;"#));
+            MockResponse::json(LONDON_CAFES)
+        })
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let a = Area {
+            name: "Cambridge".to_string(),
+            admin_level: Some(8),
+        };
+        assert_eq!(
+            a.build_query(&c),
+            r#"[out:json][timeout:25];area["name"="Cambridge"]["admin_level"="8"]->.a;node["amenity"="cafe"](area.a);out center;"#
+        );
+        assert_eq!(a.search(&c).await.unwrap().elements.len(), 2);
+
+        // The area's size is unknown, so the timeout is not scaled down
+        let c = c.with_timeout(90);
+        assert!(a.build_query(&c).starts_with("[out:json][timeout:90];area"));
+        let c = Config {
+            auto_timeout: false,
+            ..c
+        };
+        assert!(a.build_query(&c).starts_with("[out:json][timeout:90];area"));
+    }
+
+    #[tokio::test]
+    async fn test_search_all() {
+        // Answer slowly with a single node whose id is the box's southern
+        // latitude, so requests overlap when allowed to
+        let server = MockServer::with_handler(|req| {
+            let south: f64 = req.body.split(['(', ',']).nth(1).unwrap().parse().unwrap();
+            let mut resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+            resp.elements.truncate(1);
+            resp.elements[0].id = south as u64;
+            MockResponse::json(&serde_json::to_string(&resp).unwrap())
+                .delay(Duration::from_millis(50))
+        })
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let boxes: Vec<BoundingBox> = (1..=5)
+            .map(|i| BoundingBox {
+                xmin: 0.0,
+                ymin: i as f64,
+                xmax: 1.0,
+                ymax: i as f64 + 0.5,
+            })
+            .collect();
+
+        let ids: Vec<u64> = search_all(&boxes, &c, 2)
+            .await
+            .into_iter()
+            .map(|r| r.unwrap().elements[0].id)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+        assert_eq!(server.peak_in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_all_delay_between() {
+        let server = MockServer::start(vec![MockResponse::json(LONDON_CAFES)]).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .delay_between(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let boxes = vec![BoundingBox::from_point(51.5072, -0.1276, 0.5); 3];
+
+        let start = Instant::now();
+        let results = search_all(&boxes, &c, 1).await;
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_any_of_filter() {
+        let f = Filter::any_of("amenity", vec!["cafe".into(), "restaurant".into()]);
+        assert_eq!(f.to_ql(), r#"["amenity"~"^(cafe|restaurant)$"]"#);
+        let f = Filter::any_of("ref", vec!["A1.1".into(), "B(2)".into()]);
+        assert_eq!(f.to_ql(), r#"["ref"~"^(A1\\.1|B\\(2\\))$"]"#);
+
+        let c = Config::builder()
+            .tag("shop", "bakery")
+            .filter(Filter::any_of("organic", vec!["yes".into(), "only".into()]))
+            .build()
+            .unwrap();
+        let a = Around {
+            lat: 51.5,
+            lon: -0.12,
+            radius_m: 100.0,
+        };
+        assert_eq!(
+            a.build_query(&c),
+            r#"[out:json][timeout:10];node["shop"="bakery"]["organic"~"^(yes|only)$"](around:100,51.5,-0.12);out center;"#
+        );
+        assert!(Config::builder().build().is_err());
+    }
+
+    #[test]
+    fn test_tag_escaping() {
+        let c = Config::builder()
+            .tag("name", r#"Joe's "Diner"\"#)
+            .build()
+            .unwrap();
+        let a = Around {
+            lat: 51.5,
+            lon: -0.12,
+            radius_m: 100.0,
+        };
+        assert_eq!(
+            a.build_query(&c),
+            r#"[out:json][timeout:10];node["name"="Joe's \"Diner\"\\"](around:100,51.5,-0.12);out center;"#
+        );
+        let c = c.with_val(r#""];out;node["x"="y"#);
+        assert!(a
+            .build_query(&c)
+            .contains(r#"node["name"="\"];out;node[\"x\"=\"y"](around"#));
+    }
+
+    #[test]
+    fn test_maxsize_settings() {
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+        assert_eq!(settings_with_timeout(&c, None), "[out:json];");
+        let c = Config::builder()
+            .tag("amenity", "cafe")
+            .maxsize(1 << 20)
+            .build()
+            .unwrap();
+        assert_eq!(
+            settings_with_timeout(&c, None),
+            "[out:json][maxsize:1048576];"
+        );
+    }
+
+    #[cfg(feature = "xml")]
+    const LONDON_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6" generator="Overpass API 0.7.62">
+<note>The data included in this document is from www.openstreetmap.org.</note>
+<meta osm_base="2024-04-20T12:00:00Z"/>
+  <node id="1" lat="51.5072" lon="-0.1276" version="3" timestamp="2023-05-01T10:00:00Z" changeset="42" user="mapper" uid="7">
+    <tag k="amenity" v="cafe"/>
+    <tag k="name" v="Fleet &amp; Co"/>
+  </node>
+  <node id="2" lat="51.5101" lon="-0.1340" version="1"/>
+  <way id="10" version="2" user="other">
+    <center lat="51.5080" lon="-0.1290"/>
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <tag k="building" v="yes"/>
+  </way>
+  <relation id="100" version="5">
+    <center lat="51.5085" lon="-0.1300"/>
+    <member type="way" ref="10" role="outer"/>
+    <member type="node" ref="2" role=""/>
+    <tag k="type" v="multipolygon"/>
+  </relation>
+</osm>"#;
+
+    #[cfg(feature = "xml")]
+    #[test]
+    fn test_parse_xml() {
+        let osm = OsmXml::parse(LONDON_XML).unwrap();
+        assert_eq!(osm.version.as_deref(), Some("0.6"));
+        assert_eq!(osm.nodes.len(), 2);
+        let n = &osm.nodes[0];
+        assert_eq!((n.id, n.lat, n.lon), (1, 51.5072, -0.1276));
+        assert_eq!(n.tags["name"], "Fleet & Co");
+        assert_eq!(
+            n.meta,
+            XmlMeta {
+                version: Some(3),
+                timestamp: Some("2023-05-01T10:00:00Z".to_string()),
+                changeset: Some(42),
+                user: Some("mapper".to_string()),
+                uid: Some(7),
+            }
+        );
+        assert!(osm.nodes[1].tags.is_empty());
+        let w = &osm.ways[0];
+        assert_eq!(w.nodes, vec![1, 2]);
+        assert_eq!(w.center.map(|c| c.lat), Some(51.5080));
+        assert_eq!(w.tags["building"], "yes");
+        assert_eq!(w.meta.user.as_deref(), Some("other"));
+        let r = &osm.relations[0];
+        assert_eq!(r.id, 100);
+        assert_eq!(
+            r.members,
+            vec![
+                Member {
+                    osm_type: OsmType::Way,
+                    id: 10,
+                    role: "outer".to_string(),
+                },
+                Member {
+                    osm_type: OsmType::Node,
+                    id: 2,
+                    role: String::new(),
+                },
+            ]
+        );
+        assert_eq!(r.center.map(|c| c.lon), Some(-0.1300));
+        assert_eq!(r.tags["type"], "multipolygon");
+        assert_eq!(r.meta.version, Some(5));
+
+        assert!(matches!(
+            OsmXml::parse(r#"<osm><node id="x" lat="1" lon="2"/></osm>"#),
+            Err(Error::Xml(_))
+        ));
+    }
+
+    #[cfg(feature = "xml")]
+    #[tokio::test]
+    async fn test_search_xml() {
+        let server = MockServer::with_handler(|req| {
+            assert!(req.body.starts_with("[out:xml][timeout:10];"));
+            assert!(req.body.ends_with(";out meta center;"));
+            MockResponse::new(200, LONDON_XML).header("Content-Type", "application/osm3s+xml")
+        })
+        .await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let osm = b.search_xml(&c).await.unwrap();
+        assert_eq!(
+            (osm.nodes.len(), osm.ways.len(), osm.relations.len()),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_coord_formatting() {
+        assert_eq!(coord(51.4), "51.4");
+        assert_eq!(coord(-0.7690429687500001), "-0.76904297");
+        assert_eq!(coord(500.0), "500");
+        assert_eq!(coord(-1e-12), "0");
+
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+        let b = BoundingBox {
+            xmin: 1.5e-7,
+            ymin: -2e-5,
+            xmax: 0.1,
+            ymax: 0.2,
+        };
+        let q = b.build_query(&c);
+        assert!(q.contains("(-0.00002,0.00000015,0.2,0.1)"), "{}", q);
+        assert!(!q.contains("e-"));
+    }
+
+    #[test]
+    fn test_output_detail() {
+        let a = Around {
+            lat: 51.5,
+            lon: -0.1,
+            radius_m: 500.0,
+        };
+        let out = |c: Config| a.build_query(&c).rsplit(';').nth(1).unwrap().to_string();
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+        assert_eq!(out(c.clone()), "out center");
+        let tags = Config {
+            output: OutputDetail::Tags,
+            quadtile: true,
+            ..c.clone()
+        };
+        assert_eq!(out(tags), "out tags qt");
+        let skel = Config {
+            output: OutputDetail::Skel,
+            ..c.clone()
+        };
+        assert_eq!(out(skel), "out skel");
+        let capped = Config {
+            limit: Some(500),
+            quadtile: true,
+            ..c
+        };
+        assert_eq!(out(capped), "out center qt 500");
+        assert_eq!(
+            out(Config::builder().tag("a", "b").limit(10).build().unwrap()),
+            "out center 10"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_way_geometry() {
+        let body = r#"{
+            "version": 0.6,
+            "generator": "Overpass API 0.7.62",
+            "osm3s": {"timestamp_osm_base": "2024-04-20T12:00:00Z", "copyright": "OSM"},
+            "elements": [
+                {"type": "way", "id": 4, "nodes": [7, 8, 9],
+                 "bounds": {"minlat": 51.5, "minlon": -0.13, "maxlat": 51.51, "maxlon": -0.12},
+                 "geometry": [
+                     {"lat": 51.5, "lon": -0.13},
+                     {"lat": 51.505, "lon": -0.125},
+                     {"lat": 51.51, "lon": -0.12}
+                 ],
+                 "tags": {"highway": "primary"}}
+            ]
+        }"#;
+        let (server, seen) = MockServer::recording(MockResponse::json(body)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("highway", "primary")
+            .element_type(ElementType::Way)
+            .output(OutputDetail::Geometry)
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.505, -0.125, 1.0);
+        let resp = b.search_elements(&c).await.unwrap();
+        assert!(seen.lock().unwrap()[0].body.ends_with(";out geom;"));
+        match &resp.elements[0] {
+            Element::Way {
+                nodes, geometry, ..
+            } => {
+                assert_eq!(nodes.len(), geometry.len());
+                assert_eq!(
+                    geometry[1],
+                    Coordinate {
+                        lat: 51.505,
+                        lon: -0.125
+                    }
+                );
+            }
+            e => panic!("expected a way, got {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_tags() {
+        let body = r#"{
+            "version": 0.6,
+            "generator": "Overpass API 0.7.62",
+            "osm3s": {"timestamp_osm_base": "2024-04-20T12:00:00Z", "copyright": "OSM"},
+            "elements": [
+                {"type": "node", "id": 1, "tags": {"amenity": "cafe"}},
+                {"type": "way", "id": 2, "tags": {"amenity": "cafe", "building": "yes"}}
+            ]
+        }"#;
+        let server = MockServer::start(vec![MockResponse::json(body)]).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .element_type(ElementType::All)
+            .output(OutputDetail::Tags)
+            .build()
+            .unwrap();
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+
+        let resp = b.search_tags(&c).await.unwrap();
+        assert_eq!(resp.elements.len(), 2);
+        assert_eq!(resp.elements[1].osm_type, OsmType::Way);
+        assert_eq!(resp.elements[1].tags["building"], "yes");
+        // The located response cannot represent these elements
+        assert!(matches!(b.search(&c).await, Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn test_csv_settings() {
+        let c = Config {
+            format: OutputFormat::Csv(vec![
+                "::id".to_string(),
+                "amenity".to_string(),
+                "name".to_string(),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings_with_timeout(&c, None),
+            "[out:csv(::id,\"amenity\",\"name\")];"
+        );
+
+        let quoted = Config {
+            format: OutputFormat::Csv(vec![
+                "na\"me".to_string(),
+                "back\\slash".to_string(),
+                "::id)];node;out;(".to_string(),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings_with_timeout(&quoted, None),
+            r#"[out:csv("na\"me","back\\slash","::id)];node;out;(")];"#
+        );
+    }
+
+    #[test]
+    fn test_xml_settings() {
+        let c = Config {
+            format: OutputFormat::Xml,
+            ..Default::default()
+        };
+        assert_eq!(settings_with_timeout(&c, None), "[out:xml];");
+        assert_eq!(out(&c), "out meta center;");
+        assert_eq!(Error::Xml("bad".to_string()).to_string(), "xml error: bad");
+    }
+
+    #[tokio::test]
+    async fn test_search_csv() {
+        let c: Config = Config {
+            key: "amenity".to_string(),
+            val: "cafe".to_string(),
+            format: OutputFormat::Csv(vec!["::id".to_string(), "name".to_string()]),
+            ..Default::default()
+        };
+        let b = BoundingBox::from_point(51.5072, -0.1276, 0.5);
+        let csv = b.search_csv(&c).await.unwrap();
+        assert!(csv.starts_with("@id\tname"));
+    }
+
+    #[test]
+    fn test_diff_action() {
+        let n: Node =
+            serde_json::from_str(r#"{"id": 1, "lat": 51.5, "lon": -0.1, "action": "modify"}"#)
+                .unwrap();
+        assert_eq!(n.action, Some(Action::Modify));
+        assert!(n.tags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_diff_search() {
         let c: Config = Config {
             url: "https://overpass-api.de/api/interpreter".to_string(),
             timeout: 25,
             key: "amenity".to_string(),
             val: "cafe".to_string(),
+            diff_dates: Some((
+                "2023-01-01T00:00:00Z".to_string(),
+                "2024-01-01T00:00:00Z".to_string(),
+            )),
+            ..Default::default()
         };
         let b: BoundingBox = BoundingBox {
-            xmin: 51.305219521963295,
-            ymin: -0.7690429687500001,
-            xmax: 51.82219818336938,
-            ymax: 0.5273437500000064,
+            xmin: -0.7690429687500001,
+            ymin: 51.305219521963295,
+            xmax: 0.5273437500000064,
+            ymax: 51.82219818336938,
         };
         let resp = b.search(&c).await.unwrap();
-        assert!(resp.elements.len() > 0);
+        assert!(!resp.elements.is_empty());
+    }
+
+    #[test]
+    fn test_write_sql_script() {
+        let mut resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        resp.elements[0]
+            .tags
+            .insert("name".to_string(), "Fleet's \"Best\"".to_string());
+
+        let mut out = Vec::new();
+        resp.write_sql_script(&mut out, "cafes").unwrap();
+        let sql = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = sql.lines().collect();
+        assert_eq!(lines[0], "BEGIN TRANSACTION;");
+        assert!(lines[1].starts_with("CREATE TABLE IF NOT EXISTS cafes ("));
+        assert_eq!(lines.last(), Some(&"COMMIT;"));
+
+        // Read the rows back from the INSERT statements
+        let rows: Vec<(u64, f64, f64, Tags)> = lines[2..lines.len() - 1]
+            .iter()
+            .map(|l| {
+                let values = l
+                    .strip_prefix("INSERT OR REPLACE INTO cafes (id, lat, lon, tags) VALUES (")
+                    .and_then(|v| v.strip_suffix("');"))
+                    .unwrap();
+                let mut parts = values.splitn(4, ", ");
+                let mut next = || parts.next().unwrap();
+                let (id, lat, lon) = (next(), next(), next());
+                let tags = next().strip_prefix('\'').unwrap().replace("''", "'");
+                (
+                    id.parse().unwrap(),
+                    lat.parse().unwrap(),
+                    lon.parse().unwrap(),
+                    serde_json::from_str(&tags).unwrap(),
+                )
+            })
+            .collect();
+        let expected: Vec<(u64, f64, f64, Tags)> = resp
+            .elements
+            .iter()
+            .map(|n| (n.id, n.lat, n.lon, n.tags.clone()))
+            .collect();
+        assert_eq!(rows, expected);
+
+        assert!(resp.write_sql_script(io::sink(), "cafes; DROP").is_err());
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        let resp: OverpassResponse = serde_json::from_str(LONDON_CAFES).unwrap();
+        let mut out = Vec::new();
+        resp.write_ndjson(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(text.ends_with('\n'));
+        for (line, n) in lines.iter().zip(&resp.elements) {
+            let parsed: Node = serde_json::from_str(line).unwrap();
+            assert_eq!(parsed.id, n.id);
+            assert_eq!(parsed.tags, n.tags);
+        }
+
+        let mut empty = Vec::new();
+        OverpassResponse::default()
+            .write_ndjson(&mut empty)
+            .unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_primary_category() {
+        let node = |tags: &str| -> Node {
+            serde_json::from_str(&format!(
+                r#"{{"id": 1, "lat": 51.5, "lon": -0.1, "tags": {}}}"#,
+                tags
+            ))
+            .unwrap()
+        };
+
+        let n = node(r#"{"shop": "bakery", "amenity": "cafe", "name": "Gail's"}"#);
+        assert_eq!(
+            n.primary_category(),
+            Some(("amenity".to_string(), "cafe".to_string()))
+        );
+        let n = node(r#"{"building": "yes", "tourism": "museum"}"#);
+        assert_eq!(
+            n.primary_category(),
+            Some(("tourism".to_string(), "museum".to_string()))
+        );
+        let n = node(r#"{"name": "Nothing"}"#);
+        assert_eq!(n.primary_category(), None);
+    }
+
+    #[test]
+    fn test_deprecated_tags() {
+        let n: Node = serde_json::from_str(
+            r#"{"id": 1, "lat": 51.5, "lon": -0.1,
+                "tags": {"amenity": "shop", "name": "Corner", "created_by": "JOSM"}}"#,
+        )
+        .unwrap();
+        assert_eq!(n.deprecated_tags(), vec!["amenity=shop", "created_by"]);
+
+        let n: Node = serde_json::from_str(
+            r#"{"id": 2, "lat": 51.5, "lon": -0.1, "tags": {"amenity": "cafe"}}"#,
+        )
+        .unwrap();
+        assert!(n.deprecated_tags().is_empty());
+    }
+
+    #[test]
+    fn test_bounding_box_validate() {
+        let b = BoundingBox {
+            xmin: -0.7690429687500001,
+            ymin: 51.305219521963295,
+            xmax: 0.5273437500000064,
+            ymax: 51.82219818336938,
+        };
+        assert_eq!(b.validate(), Ok(()));
+
+        let inverted = BoundingBox {
+            xmin: b.xmax,
+            xmax: b.xmin,
+            ..b.clone()
+        };
+        assert_eq!(inverted.validate(), Err(BBoxError::Inverted));
+
+        let lat = BoundingBox {
+            ymax: 91.0,
+            ..b.clone()
+        };
+        assert_eq!(lat.validate(), Err(BBoxError::LatitudeOutOfRange(91.0)));
+
+        let lon = BoundingBox {
+            xmin: -181.0,
+            ..b.clone()
+        };
+        assert_eq!(lon.validate(), Err(BBoxError::LongitudeOutOfRange(-181.0)));
+
+        let nan = BoundingBox {
+            ymin: f64::NAN,
+            ..b
+        };
+        assert_eq!(nan.validate(), Err(BBoxError::NotFinite));
+    }
+
+    #[tokio::test]
+    async fn test_search_rejects_invalid_bbox() {
+        let b = BoundingBox {
+            xmin: 1.0,
+            ymin: 1.0,
+            xmax: 0.0,
+            ymax: 2.0,
+        };
+        let c = Config::builder().tag("amenity", "cafe").build().unwrap();
+        assert!(matches!(
+            b.search(&c).await,
+            Err(Error::BBox(BBoxError::Inverted))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search_rejects_invalid_around_and_area() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let a = Around {
+            lat: 51.5072,
+            lon: -0.1276,
+            radius_m: 500.0,
+        };
+        assert!(a.validate().is_ok());
+        for bad in [
+            Around {
+                lat: 91.0,
+                ..a.clone()
+            },
+            Around {
+                lon: 181.0,
+                ..a.clone()
+            },
+            Around {
+                radius_m: 0.0,
+                ..a.clone()
+            },
+            Around {
+                radius_m: f64::NAN,
+                ..a.clone()
+            },
+        ] {
+            assert!(matches!(
+                bad.search_elements(&c).await,
+                Err(Error::InvalidQuery(_))
+            ));
+        }
+
+        let area = Area {
+            name: " ".to_string(),
+            admin_level: None,
+        };
+        assert!(matches!(area.search(&c).await, Err(Error::InvalidQuery(_))));
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bounding_box_from_viewport() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+        let origin = Coordinate { lat: 0.0, lon: 0.0 };
+
+        // A single tile at zoom 0 covers the whole Mercator world
+        let b = BoundingBox::from_viewport(origin, 256, 256, 0);
+        assert!(close(b.xmin, -180.0) && close(b.xmax, 180.0));
+        assert!(close(b.ymin, -85.0511287798) && close(b.ymax, 85.0511287798));
+
+        let b = BoundingBox::from_viewport(origin, 256, 256, 1);
+        assert!(close(b.xmin, -90.0) && close(b.xmax, 90.0));
+        assert!(close(b.ymin, -66.5132604431) && close(b.ymax, 66.5132604431));
+
+        // Wider than tall, and centered on the requested point
+        let london = Coordinate {
+            lat: 51.5072,
+            lon: -0.1276,
+        };
+        let b = BoundingBox::from_viewport(london, 800, 600, 14);
+        assert!(close((b.xmin + b.xmax) / 2.0, london.lon));
+        assert!(b.ymin < london.lat && london.lat < b.ymax);
+        assert!(b.xmax - b.xmin > b.ymax - b.ymin);
+        assert_eq!(b.validate(), Ok(()));
+
+        // Cut off at the antimeridian, on either side
+        let fiji = Coordinate {
+            lat: -17.7,
+            lon: 179.99,
+        };
+        let b = BoundingBox::from_viewport(fiji, 800, 600, 10);
+        assert_eq!(b.xmax, 180.0);
+        assert!(b.xmin < fiji.lon);
+        assert_eq!(b.validate(), Ok(()));
+        let b = BoundingBox::from_viewport(
+            Coordinate {
+                lon: -179.99,
+                ..fiji
+            },
+            800,
+            600,
+            10,
+        );
+        assert_eq!(b.xmin, -180.0);
+        assert_eq!(b.validate(), Ok(()));
+
+        // Longitudes outside [-180, 180) are wrapped first
+        let b = BoundingBox::from_viewport(Coordinate { lon: 190.0, ..fiji }, 800, 600, 10);
+        assert!(close((b.xmin + b.xmax) / 2.0, -170.0));
+        assert_eq!(b.validate(), Ok(()));
+
+        // Wider than the world
+        let b = BoundingBox::from_viewport(origin, 1024, 256, 0);
+        assert_eq!((b.xmin, b.xmax), (-180.0, 180.0));
+        assert_eq!(b.validate(), Ok(()));
     }
 
     #[test]
@@ -185,5 +5448,115 @@ mod tests {
             "({}, {}, {}, {})",
             bbox.xmin, bbox.ymin, bbox.xmax, bbox.ymax
         );
+        let km = |lat, lon| haversine_km(42.361145, -71.057083, lat, lon);
+        assert!((km(bbox.ymax, -71.057083) - 10.0).abs() < 0.05);
+        assert!((km(bbox.ymin, -71.057083) - 10.0).abs() < 0.05);
+        assert!((km(42.361145, bbox.xmax) - 10.0).abs() < 0.05);
+        assert!((km(42.361145, bbox.xmin) - 10.0).abs() < 0.05);
+    }
+
+    #[tokio::test]
+    async fn test_around_set() {
+        let (server, seen) = MockServer::recording(MockResponse::json(LONDON_CAFES)).await;
+        let c = Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "bench")
+            .build()
+            .unwrap();
+        let a = AroundSet {
+            base_type: ElementType::Way,
+            base: vec![Filter::any_of(
+                "highway",
+                vec!["footway".into(), "path".into()],
+            )],
+            bbox: BoundingBox {
+                xmin: -0.2,
+                ymin: 51.4,
+                xmax: 0.1,
+                ymax: 51.6,
+            },
+            radius_m: 50.0,
+        };
+        assert_eq!(a.search(&c).await.unwrap().elements.len(), 2);
+        assert_eq!(
+            seen.lock().unwrap()[0].body,
+            "[out:json][timeout:15];way[\"highway\"~\"^(footway|path)$\"](51.4,-0.2,51.6,0.1)->.base;\
+             node[\"amenity\"=\"bench\"](around.base:50);out center;"
+        );
+
+        let no_base = AroundSet {
+            base: vec![],
+            ..a.clone()
+        };
+        assert!(matches!(no_base.validate(), Err(Error::InvalidQuery(_))));
+        let no_radius = AroundSet {
+            radius_m: 0.0,
+            ..a.clone()
+        };
+        assert!(matches!(no_radius.validate(), Err(Error::InvalidQuery(_))));
+        let inverted = AroundSet {
+            bbox: BoundingBox {
+                xmin: 0.1,
+                ymin: 51.4,
+                xmax: -0.2,
+                ymax: 51.6,
+            },
+            ..a
+        };
+        assert!(matches!(inverted.search(&c).await, Err(Error::BBox(_))));
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_bounding_box_from_center() {
+        let (lat, lon) = (51.5072, -0.1276);
+        let b = BoundingBox::from_center(lat, lon, 2.0, 1.0);
+        let width = haversine_km(lat, b.xmin, lat, b.xmax);
+        let height = haversine_km(b.ymin, lon, b.ymax, lon);
+        assert!((width - 2.0).abs() < 0.01, "width {}", width);
+        assert!((height - 1.0).abs() < 0.01, "height {}", height);
+        assert!(((b.xmin + b.xmax) / 2.0 - lon).abs() < 1e-9);
+        assert!(((b.ymin + b.ymax) / 2.0 - lat).abs() < 1e-9);
+
+        let square = BoundingBox::from_center(lat, lon, 4.0, 4.0);
+        let point = BoundingBox::from_point(lat, lon, 2.0);
+        assert_eq!(
+            (square.xmin, square.ymin, square.xmax, square.ymax),
+            (point.xmin, point.ymin, point.xmax, point.ymax)
+        );
+    }
+
+    #[test]
+    fn test_from_point_near_poles() {
+        // Longitude degrees shrink with latitude but stay finite
+        let b = BoundingBox::from_point(89.0, 10.0, 10.0);
+        assert_eq!(b.validate(), Ok(()));
+        assert!(b.xmax - b.xmin > 5.0 && b.xmax - b.xmin < 360.0);
+        assert!((b.ymax - 89.0 - 0.09).abs() < 0.001);
+
+        // Within dkm of the pole the box covers every longitude
+        for lat in [90.0, 89.95, -90.0] {
+            let b = BoundingBox::from_point(lat, 10.0, 10.0);
+            assert_eq!(b.validate(), Ok(()));
+            assert_eq!((b.xmin, b.xmax), (-180.0, 180.0));
+            assert!(b.ymin >= -90.0 && b.ymax <= 90.0);
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_metadata_timestamp() {
+        let meta = OSMMetaData {
+            timestamp_osm_base: "2023-10-01T12:00:00Z".to_string(),
+            copyright: String::new(),
+        };
+        let ts = meta.timestamp().unwrap();
+        assert_eq!(ts.to_rfc3339(), "2023-10-01T12:00:00+00:00");
+
+        let bad = OSMMetaData {
+            timestamp_osm_base: "yesterday".to_string(),
+            copyright: String::new(),
+        };
+        assert!(bad.timestamp().is_none());
     }
 }
@@ -0,0 +1,88 @@
+//! Throttling shared between requests
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Instant};
+
+/// Spaces out requests so that consecutive calls to [`RateLimiter::wait`]
+/// return at least `interval` apart, plus a random delay of up to `jitter`
+/// so that waiting tasks don't all fire at once.
+///
+/// Wrap it in an `Arc` and set it on a config with `with_shared_limiter` to
+/// throttle every clone of that config together.
+///
+/// # Example
+/// ```rust
+/// use osm_rs::nominatim::Config;
+/// use osm_rs::ratelimit::RateLimiter;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let limiter = Arc::new(
+///     RateLimiter::new(Duration::from_secs(1)).with_jitter(Duration::from_millis(200)),
+/// );
+/// let c = Config::default().with_shared_limiter(limiter);
+/// let for_task = c.clone(); // waits on the same limiter
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    jitter: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            jitter: Duration::ZERO,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Add a random delay of up to `jitter` to each wait
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Wait for the next free slot
+    pub async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        sleep_until(*next + self.random_jitter()).await;
+        *next = Instant::now() + self.interval;
+    }
+
+    /// Random duration between zero and `jitter`
+    fn random_jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        // Each RandomState is freshly keyed, which is enough randomness to
+        // spread requests out
+        let r = RandomState::new().build_hasher().finish();
+        self.jitter.mul_f64(r as f64 / u64::MAX as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait() {
+        let limiter = RateLimiter::new(Duration::from_millis(20));
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.wait().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        let jittered = RateLimiter::new(Duration::ZERO).with_jitter(Duration::from_millis(5));
+        let jitters: Vec<Duration> = (0..20).map(|_| jittered.random_jitter()).collect();
+        assert!(jitters.iter().all(|j| *j <= Duration::from_millis(5)));
+        assert!(jitters.iter().any(|j| *j != jitters[0]));
+        assert_eq!(limiter.random_jitter(), Duration::ZERO);
+    }
+}
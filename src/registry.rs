@@ -0,0 +1,94 @@
+//! Per-host request defaults applied to every search
+//!
+//! # Example
+//! ```rust
+//! use osm_rs::registry::{EndpointDefaults, EndpointRegistry};
+//!
+//! EndpointRegistry::global().register(
+//!     "nominatim.openstreetmap.org",
+//!     EndpointDefaults {
+//!         user_agent: Some("my-app/1.0 (me@example.com)".to_string()),
+//!         headers: vec![("Accept-Language".to_string(), "en".to_string())],
+//!     },
+//! );
+//! ```
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// Defaults for requests to one host. A `user_agent` set on a config takes
+/// precedence over the one registered here.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EndpointDefaults {
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every request
+    pub headers: Vec<(String, String)>,
+}
+
+/// Defaults keyed by host, or by `host:port` to single out one port
+#[derive(Debug, Default)]
+pub struct EndpointRegistry {
+    hosts: RwLock<HashMap<String, EndpointDefaults>>,
+}
+
+impl EndpointRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry consulted by `search`
+    pub fn global() -> &'static EndpointRegistry {
+        static GLOBAL: OnceLock<EndpointRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(EndpointRegistry::new)
+    }
+
+    pub fn register(&self, host: impl Into<String>, defaults: EndpointDefaults) {
+        self.hosts.write().unwrap().insert(host.into(), defaults);
+    }
+
+    pub fn unregister(&self, host: &str) -> Option<EndpointDefaults> {
+        self.hosts.write().unwrap().remove(host)
+    }
+
+    /// Defaults for the host of `url`, preferring a `host:port` entry
+    pub fn get(&self, url: &str) -> Option<EndpointDefaults> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?;
+        let hosts = self.hosts.read().unwrap();
+        url.port()
+            .and_then(|port| hosts.get(&format!("{}:{}", host, port)))
+            .or_else(|| hosts.get(host))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_by_host() {
+        let r = EndpointRegistry::new();
+        let ua = |s: &str| EndpointDefaults {
+            user_agent: Some(s.to_string()),
+            ..Default::default()
+        };
+        r.register("example.com", ua("any-port"));
+        r.register("example.com:8080", ua("port-8080"));
+
+        let get = |url| r.get(url).and_then(|d| d.user_agent);
+        assert_eq!(
+            get("https://example.com/search").as_deref(),
+            Some("any-port")
+        );
+        assert_eq!(
+            get("http://example.com:8080/").as_deref(),
+            Some("port-8080")
+        );
+        assert_eq!(get("http://example.org/"), None);
+        assert_eq!(get("not a url"), None);
+
+        r.unregister("example.com");
+        assert_eq!(get("https://example.com/search"), None);
+    }
+}
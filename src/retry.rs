@@ -0,0 +1,199 @@
+//! Retrying requests that failed transiently
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often and how long to retry a request that was throttled, hit an
+/// overloaded server or could not connect. Waits for `Retry-After` when the
+/// server sends one and backs off exponentially from `base_delay` otherwise.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Longest backoff between attempts. A `Retry-After` is honored even
+    /// when longer.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff after failed attempt number `attempt`, counting from 1:
+    /// `base_delay` doubled for each earlier attempt, at most `max_delay`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |d| d.min(self.max_delay))
+    }
+}
+
+/// Call `f` until it succeeds, fails permanently or `policy.max_attempts`
+/// are used up, returning the last result
+pub(crate) async fn with_retry<T, F, Fut>(policy: &RetryPolicy, mut f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut attempt = 1;
+    loop {
+        let res = f().await;
+        let delay = match &res {
+            Err(Error::Unavailable { retry_after, .. }) => {
+                retry_after.unwrap_or_else(|| policy.backoff(attempt))
+            }
+            Err(Error::Http(e)) if e.is_connect() || e.is_timeout() => policy.backoff(attempt),
+            _ => return res,
+        };
+        if attempt >= policy.max_attempts {
+            return res;
+        }
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Parse a `Retry-After` value, either seconds or an HTTP date such as
+/// `Wed, 21 Oct 2015 07:28:00 GMT`, into the time left to wait after `now`
+pub(crate) fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(parse_http_date(value)?);
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// Seconds since the epoch of an IMF-fixdate, e.g.
+/// `Wed, 21 Oct 2015 07:28:00 GMT`
+fn parse_http_date(s: &str) -> Option<u64> {
+    let (_, rest) = s.split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let day: i64 = day.parse().ok()?;
+    let year: i64 = year.parse().ok()?;
+    let hms: Vec<u64> = time
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let [h, m, sec] = hms[..] else {
+        return None;
+    };
+    if !(1..=31).contains(&day) || h > 23 || m > 59 || sec > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86400 + h * 3600 + m * 60 + sec)
+}
+
+/// Days between 1970-01-01 and the given proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = UNIX_EPOCH + Duration::from_secs(1445412470);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Foo 2015 07:28:00 GMT", now),
+            None
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let p = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+        let delays: Vec<u64> = (1..=5).map(|a| p.backoff(a).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+        assert_eq!(p.backoff(100), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_with_retry() {
+        let p = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        let calls = AtomicU32::new(0);
+        let res: Result<(), Error> = with_retry(&p, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(Error::Unavailable {
+                status: 429,
+                retry_after: Some(Duration::from_millis(1)),
+            })
+        })
+        .await;
+        assert!(matches!(res, Err(Error::Unavailable { status: 429, .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        calls.store(0, Ordering::SeqCst);
+        let res = with_retry(&p, || async {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            if n == 0 {
+                Err(Error::Unavailable {
+                    status: 503,
+                    retry_after: None,
+                })
+            } else {
+                Ok(n)
+            }
+        })
+        .await;
+        assert_eq!(res.unwrap(), 1);
+
+        calls.store(0, Ordering::SeqCst);
+        let res: Result<(), Error> = with_retry(&p, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(Error::Empty)
+        })
+        .await;
+        assert!(matches!(res, Err(Error::Empty)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,276 @@
+//! One client, rate limiter and user agent shared by Nominatim and Overpass
+//!
+//! # Example
+//! ```rust
+//! use osm_rs::nominatim::Geocode;
+//! use osm_rs::overpass;
+//! use osm_rs::Session;
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let s = Session::new("my-app/1.0 (me@example.com)", Duration::from_secs(1)).unwrap();
+//!     let place = s.geocode(&Geocode::new("Boston".to_string())).await.unwrap();
+//!     let c = overpass::Config::builder().tag("amenity", "cafe").build().unwrap();
+//!     let cafes = s.overpass(&place[0].bbox(), &c).await.unwrap();
+//! }
+//! ```
+use crate::nominatim::{self, Geocode, GeocodeResponse, ReverseGeocode};
+use crate::overpass::{self, BoundingBox, OverpassResponse, Search};
+use crate::ratelimit::RateLimiter;
+use crate::Error;
+use reqwest::Client;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Public Nominatim instance, without the endpoint path
+const NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+
+/// Sends the requests of both APIs through one connection pool, identifying
+/// as one user agent and waiting on one [`RateLimiter`]. Cloning is cheap
+/// and the clones share all three.
+#[derive(Debug, Clone)]
+pub struct Session {
+    client: Client,
+    limiter: Arc<RateLimiter>,
+    nominatim_url: String,
+    nominatim: nominatim::Config,
+}
+
+impl Session {
+    /// Session identifying as `user_agent` that spaces requests to either
+    /// API at least `interval` apart
+    pub fn new(user_agent: &str, interval: Duration) -> Result<Self, Error> {
+        let client = Client::builder().user_agent(user_agent).build()?;
+        Ok(Self {
+            client,
+            limiter: Arc::new(RateLimiter::new(interval)),
+            nominatim_url: NOMINATIM_URL.to_string(),
+            nominatim: nominatim::Config::default(),
+        })
+    }
+
+    /// Use the Nominatim instance at `url`, e.g.
+    /// `https://nominatim.openstreetmap.org`, for `geocode` and `reverse`
+    pub fn with_nominatim_url(mut self, url: impl Into<String>) -> Self {
+        self.nominatim_url = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Base the Nominatim configs of this session on `config`, keeping e.g.
+    /// its `timeout` and `email`. Its `url` is replaced by the session's
+    /// Nominatim URL and endpoint. The shared client and limiter stand in
+    /// for `user_agent`, `max_redirects`, `client` and `limiter`, so a
+    /// config setting any of them is rejected with [`Error::InvalidConfig`].
+    pub fn with_nominatim_config(mut self, config: nominatim::Config) -> Result<Self, Error> {
+        reject_overridden([
+            ("user_agent", config.user_agent.is_some()),
+            ("max_redirects", config.max_redirects.is_some()),
+            ("client", config.client.is_some()),
+            ("limiter", config.limiter.is_some()),
+        ])?;
+        self.nominatim = config;
+        Ok(self)
+    }
+
+    /// The shared HTTP client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Nominatim config for `endpoint`, e.g. `search`, sending through this
+    /// session, see [`Session::with_nominatim_config`]
+    pub fn nominatim_config(&self, endpoint: &str) -> nominatim::Config {
+        nominatim::Config {
+            url: format!("{}/{}", self.nominatim_url, endpoint),
+            ..self.nominatim.clone()
+        }
+        .with_client(self.client.clone())
+        .with_shared_limiter(self.limiter.clone())
+    }
+
+    /// Copy of `config` sending through this session. The shared client and
+    /// limiter stand in for `user_agent`, `max_redirects`, `compression`,
+    /// `client` and `limiter`, so a config changing any of them from its
+    /// default is rejected with [`Error::InvalidConfig`].
+    pub fn overpass_config(&self, config: &overpass::Config) -> Result<overpass::Config, Error> {
+        reject_overridden([
+            ("user_agent", config.user_agent.is_some()),
+            ("max_redirects", config.max_redirects.is_some()),
+            ("compression", !config.compression),
+            ("client", config.client.is_some()),
+            ("limiter", config.limiter.is_some()),
+        ])?;
+        Ok(config
+            .with_client(self.client.clone())
+            .with_shared_limiter(self.limiter.clone()))
+    }
+
+    /// Geocode `query`, see [`Geocode::search`]
+    pub async fn geocode(&self, query: &Geocode) -> Result<Vec<GeocodeResponse>, Error> {
+        query.search(&self.nominatim_config("search")).await
+    }
+
+    /// Reverse geocode `query`, see [`ReverseGeocode::search`]
+    pub async fn reverse(&self, query: &ReverseGeocode) -> Result<GeocodeResponse, Error> {
+        query.search(&self.nominatim_config("reverse")).await
+    }
+
+    /// Search `bbox` for elements matching `config`, see
+    /// [`Search::search`]. The URL and filters come from `config`.
+    pub async fn overpass(
+        &self,
+        bbox: &BoundingBox,
+        config: &overpass::Config,
+    ) -> Result<OverpassResponse, Error> {
+        bbox.search(&self.overpass_config(config)?).await
+    }
+}
+
+/// Fail with [`Error::InvalidConfig`] naming the settings that are set
+/// although the session overrides them
+fn reject_overridden<const N: usize>(settings: [(&str, bool); N]) -> Result<(), Error> {
+    let overridden: Vec<&str> = settings
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect();
+    if !overridden.is_empty() {
+        return Err(Error::InvalidConfig(format!(
+            "set by the session: {}",
+            overridden.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use std::sync::Mutex;
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn test_session() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let server = MockServer::with_handler(move |req| {
+            log.lock().unwrap().push(req.clone());
+            let nominatim = include_str!("../fixtures/nominatim_boston.json");
+            if req.target.starts_with("/search") {
+                MockResponse::json(&format!("[{}]", nominatim))
+            } else if req.target.starts_with("/reverse") {
+                MockResponse::json(nominatim)
+            } else {
+                MockResponse::json(include_str!("../fixtures/overpass_london_cafes.json"))
+            }
+        })
+        .await;
+        let s = Session::new("session-test/1.0", Duration::from_millis(100))
+            .unwrap()
+            .with_nominatim_url(server.url("/"));
+        let oc = overpass::Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+
+        let start = Instant::now();
+        let place = s
+            .geocode(&Geocode::new("Boston".to_string()))
+            .await
+            .unwrap();
+        let back = s
+            .reverse(&ReverseGeocode::new(place[0].lat, place[0].lon))
+            .await
+            .unwrap();
+        assert_eq!(back.osm_id, place[0].osm_id);
+        let cafes = s.overpass(&place[0].bbox(), &oc).await.unwrap();
+        assert_eq!(cafes.elements.len(), 2);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        let seen = seen.lock().unwrap();
+        let targets: Vec<&str> = seen
+            .iter()
+            .map(|r| r.target.split('?').next().unwrap())
+            .collect();
+        assert_eq!(targets, ["/search", "/reverse", "/api/interpreter"]);
+        assert!(seen
+            .iter()
+            .all(|r| r.header("user-agent") == Some("session-test/1.0")));
+    }
+    #[tokio::test]
+    async fn test_session_nominatim_config() {
+        let (server, seen) = MockServer::recording(MockResponse::json(&format!(
+            "[{}]",
+            include_str!("../fixtures/nominatim_boston.json")
+        )))
+        .await;
+        let base = nominatim::Config {
+            email: Some("me@example.com".to_string()),
+            timeout: 5,
+            ..Default::default()
+        };
+        let s = Session::new("session-test/1.0", Duration::from_millis(1))
+            .unwrap()
+            .with_nominatim_url(server.url("/"))
+            .with_nominatim_config(base)
+            .unwrap();
+        let c = s.nominatim_config("search");
+        assert_eq!(c.url, server.url("/search"));
+        assert_eq!(c.timeout, 5);
+        s.geocode(&Geocode::new("Boston".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            seen.lock().unwrap()[0].query("email"),
+            Some("me%40example.com")
+        );
+
+        let overridden = nominatim::Config {
+            user_agent: Some("other/1.0".to_string()),
+            max_redirects: Some(0),
+            ..Default::default()
+        };
+        match s.with_nominatim_config(overridden) {
+            Err(Error::InvalidConfig(m)) => assert!(m.ends_with("user_agent, max_redirects")),
+            r => panic!("unexpected {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_overpass_config() {
+        let (server, seen) = MockServer::recording(MockResponse::json(include_str!(
+            "../fixtures/overpass_london_cafes.json"
+        )))
+        .await;
+        let s = Session::new("session-test/1.0", Duration::from_millis(1)).unwrap();
+        let oc = overpass::Config::builder()
+            .url(server.url("/api/interpreter"))
+            .tag("amenity", "cafe")
+            .build()
+            .unwrap();
+        let b = BoundingBox {
+            xmin: -0.13,
+            ymin: 51.50,
+            xmax: -0.12,
+            ymax: 51.51,
+        };
+        s.overpass(&b, &oc).await.unwrap();
+        assert_eq!(
+            seen.lock().unwrap()[0].header("user-agent"),
+            Some("session-test/1.0")
+        );
+
+        let overridden = overpass::Config {
+            user_agent: Some("other/1.0".to_string()),
+            compression: false,
+            ..oc
+        };
+        match s.overpass(&b, &overridden).await {
+            Err(Error::InvalidConfig(m)) => assert!(m.ends_with("user_agent, compression")),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+}
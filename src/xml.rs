@@ -0,0 +1,244 @@
+//! Minimal XML reader, enough for Overpass `[out:xml]` responses
+//!
+//! Only elements and their attributes are reported. Text, CDATA sections,
+//! comments, processing instructions and doctypes are skipped. Every end tag
+//! must close the element opened last.
+
+/// Start or end of an element
+#[derive(Debug, PartialEq)]
+pub(crate) enum Event {
+    /// `<name a="1">`, or `<name a="1"/>` when `empty`
+    Start {
+        name: String,
+        attrs: Vec<(String, String)>,
+        empty: bool,
+    },
+    /// `</name>`
+    End(String),
+}
+
+/// Split `doc` into element events
+pub(crate) fn parse(doc: &str) -> Result<Vec<Event>, String> {
+    let mut events = Vec::new();
+    let mut open: Vec<String> = Vec::new();
+    let mut rest = doc;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(r) = rest.strip_prefix("<!--") {
+            let end = r.find("-->").ok_or("unterminated comment")?;
+            rest = &r[end + 3..];
+            continue;
+        }
+        if let Some(r) = rest.strip_prefix("<![CDATA[") {
+            let end = r.find("]]>").ok_or("unterminated CDATA section")?;
+            rest = &r[end + 3..];
+            continue;
+        }
+        if let Some(r) = rest.strip_prefix("<?") {
+            let end = r.find("?>").ok_or("unterminated processing instruction")?;
+            rest = &r[end + 2..];
+            continue;
+        }
+        if rest.starts_with("<!") {
+            let end = declaration_end(rest).ok_or("unterminated declaration")?;
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let end = tag_end(rest).ok_or("unterminated tag")?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim();
+            match open.pop() {
+                Some(o) if o == name => {}
+                Some(o) => return Err(format!("`</{}>` closes `<{}>`", name, o)),
+                None => return Err(format!("`</{}>` without a start tag", name)),
+            }
+            events.push(Event::End(name.to_string()));
+            continue;
+        }
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(t) => (t, true),
+            None => (tag, false),
+        };
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_string();
+        if !empty {
+            open.push(name.clone());
+        }
+        events.push(Event::Start {
+            name,
+            attrs: attributes(&tag[name_end..])?,
+            empty,
+        });
+    }
+    match open.pop() {
+        Some(o) => Err(format!("unclosed `<{}>`", o)),
+        None => Ok(events),
+    }
+}
+
+/// Index of the `>` closing the declaration, e.g. `<!DOCTYPE ...>`, opened
+/// at the start of `s`, skipping any inside an internal subset in brackets
+/// or in quotes
+fn declaration_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, '>') if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Index of the `>` closing the tag opened at the start of `s`, skipping
+/// any inside quoted attribute values
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn attributes(mut s: &str) -> Result<Vec<(String, String)>, String> {
+    let mut attrs = Vec::new();
+    loop {
+        s = s.trim_start();
+        if s.is_empty() {
+            return Ok(attrs);
+        }
+        let eq = s
+            .find('=')
+            .ok_or_else(|| format!("attribute without value in `{}`", s))?;
+        let key = s[..eq].trim().to_string();
+        let v = s[eq + 1..].trim_start();
+        let quote = v
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("unquoted value for `{}`", key))?;
+        let close = v[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated value for `{}`", key))?;
+        attrs.push((key, unescape(&v[1..close + 1])));
+        s = &v[close + 2..];
+    }
+}
+
+/// Replace the predefined and numeric character references
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').and_then(|semi| {
+            let c = match &rest[1..semi] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                e => {
+                    let code = match e.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => e.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, semi))
+        });
+        match decoded {
+            Some((c, semi)) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let doc = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- generated -->
+<osm version="0.6">
+  <node id="1" name='a &amp; b &#233; &gt; "c"'/>
+  <note>text is skipped</note>
+</osm>"#;
+        let start = |name: &str, attrs: &[(&str, &str)], empty| Event::Start {
+            name: name.to_string(),
+            attrs: attrs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            empty,
+        };
+        assert_eq!(
+            parse(doc).unwrap(),
+            vec![
+                start("osm", &[("version", "0.6")], false),
+                start("node", &[("id", "1"), ("name", "a & b é > \"c\"")], true),
+                start("note", &[], false),
+                Event::End("note".to_string()),
+                Event::End("osm".to_string()),
+            ]
+        );
+        assert!(parse("<node id=1/>").is_err());
+        assert!(parse("<node id=\"1\"").is_err());
+    }
+
+    #[test]
+    fn test_skip_markup_containing_gt() {
+        let doc = r#"<!DOCTYPE osm [<!ENTITY x "a>b">]>
+<osm><!-- a > b --><note><![CDATA[<fake attr=">"/> ]] >]]></note></osm>"#;
+        let names: Vec<String> = parse(doc)
+            .unwrap()
+            .into_iter()
+            .map(|e| match e {
+                Event::Start { name, .. } => name,
+                Event::End(name) => format!("/{}", name),
+            })
+            .collect();
+        assert_eq!(names, ["osm", "note", "/note", "/osm"]);
+        assert!(parse("<osm><![CDATA[ > </osm>").is_err());
+    }
+
+    #[test]
+    fn test_mismatched_tags() {
+        assert_eq!(
+            parse("<osm><node id=\"1\"></osm>").unwrap_err(),
+            "`</osm>` closes `<node>`"
+        );
+        assert_eq!(
+            parse("<osm></node>").unwrap_err(),
+            "`</node>` closes `<osm>`"
+        );
+        assert_eq!(parse("</osm>").unwrap_err(), "`</osm>` without a start tag");
+        assert_eq!(parse("<osm><way>").unwrap_err(), "unclosed `<way>`");
+    }
+}